@@ -0,0 +1,171 @@
+use cozy_chess::{Board, Move};
+
+use crate::fen::parse_uci_move;
+use crate::san::from_san;
+use crate::{Eval, Frozenight, PositionError, TimeConstraint};
+
+/// A test position from an EPD record, with the moves it expects to be played or avoided.
+#[derive(Clone, Debug)]
+pub struct Epd {
+    pub board: Board,
+    pub id: Option<String>,
+    /// The `bm` operation. If not empty, the engine must play one of these.
+    pub best_moves: Vec<Move>,
+    /// The `am` operation. The engine must not play any of these.
+    pub avoid_moves: Vec<Move>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EpdResult {
+    pub id: Option<String>,
+    pub best_move: Move,
+    pub eval: Eval,
+    pub passed: bool,
+}
+
+/// Parses an EPD record: the first four FEN fields followed by `;`-terminated operations. Only
+/// the `id`, `bm` and `am` operations are used. Moves are in SAN, though UCI notation is accepted
+/// too.
+pub fn parse_epd(line: &str) -> Result<Epd, PositionError> {
+    let invalid = || PositionError::InvalidEpd(line.to_owned());
+
+    let mut fields = line.trim().splitn(5, ' ');
+    let mut fen = String::new();
+    for _ in 0..4 {
+        fen += fields.next().ok_or_else(invalid)?;
+        fen += " ";
+    }
+    fen += "0 1";
+    let board: Board = fen.parse().map_err(|_| PositionError::InvalidFen(fen))?;
+
+    let mut epd = Epd {
+        board,
+        id: None,
+        best_moves: vec![],
+        avoid_moves: vec![],
+    };
+    for op in operations(fields.next().unwrap_or("")) {
+        let op = op.trim();
+        let (opcode, operands) = op.split_once(char::is_whitespace).unwrap_or((op, ""));
+        match opcode {
+            "id" => epd.id = Some(operands.trim().trim_matches('"').to_owned()),
+            "bm" | "am" => {
+                let parse =
+                    |mv| from_san(&epd.board, mv).or_else(|_| parse_uci_move(&epd.board, mv));
+                let moves = operands
+                    .split_whitespace()
+                    .map(parse)
+                    .collect::<Result<Vec<_>, _>>()?;
+                match opcode {
+                    "bm" => epd.best_moves = moves,
+                    _ => epd.avoid_moves = moves,
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(epd)
+}
+
+/// Splits EPD operations on the `;` terminating each one, except inside quoted strings.
+fn operations(ops: &str) -> impl Iterator<Item = &str> {
+    let mut quoted = false;
+    ops.split(move |c: char| {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        c == ';' && !quoted
+    })
+}
+
+impl Epd {
+    pub fn is_solved_by(&self, mv: Move) -> bool {
+        (self.best_moves.is_empty() || self.best_moves.contains(&mv))
+            && !self.avoid_moves.contains(&mv)
+    }
+}
+
+impl Frozenight {
+    /// Searches each position within `budget` from a fresh game, checking the move found against
+    /// the position's expected moves.
+    pub fn analyze_epd(&mut self, positions: &[Epd], budget: TimeConstraint) -> Vec<EpdResult> {
+        positions
+            .iter()
+            .map(|epd| {
+                self.new_game();
+                self.set_position(epd.board.clone(), std::iter::empty());
+                let info = self.search(budget, |_| {});
+                EpdResult {
+                    id: epd.id.clone(),
+                    best_move: info.best_move,
+                    eval: info.eval,
+                    passed: epd.is_solved_by(info.best_move),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves(mvs: &[&str]) -> Vec<Move> {
+        mvs.iter().map(|mv| mv.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn bm_am_id() {
+        let epd = parse_epd(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm e4 d4 Nf3; am f3 g4; id \"start\";",
+        )
+        .unwrap();
+        assert!(epd.board.same_position(&Board::default()));
+        assert_eq!(epd.id.as_deref(), Some("start"));
+        assert_eq!(epd.best_moves, moves(&["e2e4", "d2d4", "g1f3"]));
+        assert_eq!(epd.avoid_moves, moves(&["f2f3", "g2g4"]));
+    }
+
+    #[test]
+    fn quoted_id() {
+        let epd =
+            parse_epd("4k3/8/8/8/8/8/8/R3K3 w Q - id \"rook; with  spaces\"; bm Ra8+;").unwrap();
+        assert_eq!(epd.id.as_deref(), Some("rook; with  spaces"));
+        assert_eq!(epd.best_moves, moves(&["a1a8"]));
+    }
+
+    #[test]
+    fn uci_moves() {
+        let epd = parse_epd("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - bm e1g1; am O-O-O;").unwrap();
+        assert_eq!(epd.best_moves, moves(&["e1h1"]));
+        assert_eq!(epd.avoid_moves, moves(&["e1a1"]));
+        assert_eq!(epd.id, None);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(matches!(
+            parse_epd("4k3/8/8/8/8/8/8/4K3 w"),
+            Err(PositionError::InvalidEpd(_))
+        ));
+        assert!(matches!(
+            parse_epd("4k3/8/8/8/8/8/8/4K3 w - - bm Qh5;"),
+            Err(PositionError::InvalidMove(_))
+        ));
+    }
+
+    #[test]
+    fn analyze() {
+        let epd = parse_epd("6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; id \"back rank\";").unwrap();
+        let budget = TimeConstraint {
+            depth: 4,
+            ..TimeConstraint::INFINITE
+        };
+        let results = Frozenight::new(1).analyze_epd(&[epd], budget);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id.as_deref(), Some("back rank"));
+        assert_eq!(results[0].best_move, "a1a8".parse().unwrap());
+        assert!(results[0].passed);
+    }
+}
@@ -1,4 +1,5 @@
 use bytemuck::{Pod, Zeroable};
+use cozy_chess::Color;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Pod, Zeroable, Default)]
 #[repr(transparent)]
@@ -72,6 +73,70 @@ impl Eval {
     pub fn raw(self) -> i16 {
         self.0
     }
+
+    /// Converts an eval relative to `side_to_move` into one relative to white.
+    ///
+    /// ```
+    /// use cozy_chess::Color;
+    /// use frozenight::Eval;
+    ///
+    /// assert_eq!(Eval::new(100).white_relative(Color::White), Eval::new(100));
+    /// assert_eq!(Eval::new(100).white_relative(Color::Black), Eval::new(-100));
+    ///
+    /// // black to move and getting mated is white mating, in just as many plys
+    /// let black_mated = (-Eval::MATE).add_time(3);
+    /// assert_eq!(black_mated.white_relative(Color::Black), Eval::MATE.add_time(3));
+    /// assert_eq!(Eval::new(-40).white_relative(Color::Black), Eval::new(40));
+    /// ```
+    pub fn white_relative(self, side_to_move: Color) -> Self {
+        match side_to_move {
+            Color::White => self,
+            Color::Black => -self,
+        }
+    }
+
+    /// Estimates the win, draw and loss probabilities in permille using a logistic model.
+    ///
+    /// ```
+    /// use frozenight::Eval;
+    ///
+    /// let (w, d, l) = Eval::new(250).to_wdl();
+    /// assert_eq!(w + d + l, 1000);
+    /// assert_eq!(Eval::DRAW.to_wdl().0, Eval::DRAW.to_wdl().2);
+    /// assert!(Eval::new(10_000).to_wdl().0 > 990);
+    /// assert_eq!(Eval::MATE.to_wdl(), (1000, 0, 0));
+    /// assert_eq!((-Eval::MATE).to_wdl(), (0, 0, 1000));
+    /// ```
+    pub fn to_wdl(self) -> (u16, u16, u16) {
+        // how far from even an eval has to be for a win to be as likely as a draw, and how quickly
+        // the probabilities change around there
+        const DRAW_MARGIN: f64 = 500.0;
+        const SCALE: f64 = 250.0;
+
+        if self.is_conclusive() {
+            return match self > Eval::DRAW {
+                true => (1000, 0, 0),
+                false => (0, 0, 1000),
+            };
+        }
+        let logistic = |x: f64| 1.0 / (1.0 + ((DRAW_MARGIN - x) / SCALE).exp());
+        let win = (1000.0 * logistic(self.0 as f64)).round() as u16;
+        let loss = (1000.0 * logistic(-self.0 as f64)).round() as u16;
+        (win, 1000 - win - loss, loss)
+    }
+}
+
+/// The steps of the static evaluation of a position.
+#[derive(Clone, Copy, Debug)]
+pub struct EvalTrace {
+    /// The raw output of the network.
+    pub network: Eval,
+    /// How much of the network output is kept as the fifty-move rule approaches, out of 100.
+    pub fifty_move_scale: i32,
+    /// How much is kept after that in drawish endgames, out of 64.
+    pub endgame_scale: i32,
+    /// The final static eval.
+    pub eval: Eval,
 }
 
 impl std::ops::Neg for Eval {
@@ -82,11 +147,20 @@ impl std::ops::Neg for Eval {
     }
 }
 
+/// Adding margins yields an inconclusive eval, even for large margins or conclusive evals.
+///
+/// ```
+/// use frozenight::Eval;
+///
+/// assert_eq!(Eval::MATE + 10_000, Eval::MAX_INCONCLUSIVE);
+/// assert_eq!(-Eval::MATE - 10_000, -Eval::MAX_INCONCLUSIVE);
+/// assert_eq!(Eval::new(100) + i16::MAX, Eval::MAX_INCONCLUSIVE);
+/// ```
 impl std::ops::Add<i16> for Eval {
     type Output = Eval;
 
     fn add(self, rhs: i16) -> Self::Output {
-        Eval::new(self.0 + rhs)
+        Eval::new(self.0.saturating_add(rhs))
     }
 }
 
@@ -94,7 +168,7 @@ impl std::ops::Sub<i16> for Eval {
     type Output = Eval;
 
     fn sub(self, rhs: i16) -> Self::Output {
-        Eval::new(self.0 - rhs)
+        Eval::new(self.0.saturating_sub(rhs))
     }
 }
 
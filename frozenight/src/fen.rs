@@ -0,0 +1,170 @@
+use cozy_chess::{Board, Color, File, Move, Rank, Square};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PositionError {
+    InvalidFen(String),
+    InvalidMove(String),
+    IllegalMove(String),
+    InvalidEpd(String),
+}
+
+impl std::fmt::Display for PositionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PositionError::InvalidFen(fen) => write!(f, "invalid FEN: {}", fen),
+            PositionError::InvalidMove(mv) => write!(f, "invalid move: {}", mv),
+            PositionError::IllegalMove(mv) => write!(f, "illegal move: {}", mv),
+            PositionError::InvalidEpd(epd) => write!(f, "invalid EPD: {}", epd),
+        }
+    }
+}
+
+impl std::error::Error for PositionError {}
+
+/// Parses a FEN and plays the given moves on it. Moves are in UCI notation, with castling written
+/// as the king moving two squares.
+pub fn parse_position(fen: &str, moves: &[&str]) -> Result<Board, PositionError> {
+    let mut board: Board = fen
+        .parse()
+        .map_err(|_| PositionError::InvalidFen(fen.to_owned()))?;
+
+    for &mv_str in moves {
+        let mv = parse_uci_move(&board, mv_str)?;
+        board.play_unchecked(mv);
+    }
+
+    Ok(board)
+}
+
+/// Parses a legal move in UCI notation, with castling written as the king moving two squares.
+pub(crate) fn parse_uci_move(board: &Board, mv_str: &str) -> Result<Move, PositionError> {
+    let mv: Move = mv_str
+        .parse()
+        .map_err(|_| PositionError::InvalidMove(mv_str.to_owned()))?;
+    let mv = from_uci_castling(board, mv, false);
+
+    match board.is_legal(mv) {
+        true => Ok(mv),
+        false => Err(PositionError::IllegalMove(mv_str.to_owned())),
+    }
+}
+
+/// Converts a UCI move to the cozy-chess encoding, where castling is the king capturing its own
+/// rook. Unless `chess960` is set, UCI writes castling as the king moving two squares, which is
+/// only possible with a king on the e-file and the castling rook in the corner.
+pub fn from_uci_castling(board: &Board, mut mv: Move, chess960: bool) -> Move {
+    if chess960 {
+        return mv;
+    }
+    let us = board.side_to_move();
+    let back_rank = match us {
+        Color::White => Rank::First,
+        Color::Black => Rank::Eighth,
+    };
+    if mv.from != Square::new(File::E, back_rank)
+        || board.king(us) != mv.from
+        || mv.to.rank() != back_rank
+    {
+        return mv;
+    }
+
+    let rights = board.castle_rights(us);
+    if mv.to.file() == File::G && rights.short == Some(File::H) {
+        mv.to = Square::new(File::H, back_rank);
+    } else if mv.to.file() == File::C && rights.long == Some(File::A) {
+        mv.to = Square::new(File::A, back_rank);
+    }
+    mv
+}
+
+/// Converts a move from the cozy-chess encoding to UCI, the inverse of [`from_uci_castling`].
+pub fn to_uci_castling(board: &Board, mut mv: Move, chess960: bool) -> Move {
+    if chess960 {
+        return mv;
+    }
+    if board.color_on(mv.from) == board.color_on(mv.to) {
+        if mv.to.file() > mv.from.file() {
+            mv.to = Square::new(File::G, mv.to.rank());
+        } else {
+            mv.to = Square::new(File::C, mv.to.rank());
+        }
+    }
+    mv
+}
+
+pub fn board_to_fen(board: &Board) -> String {
+    board.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use cozy_chess::Piece;
+
+    use super::*;
+
+    const CASTLING: &str = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+
+    #[test]
+    fn invalid_fen() {
+        assert_eq!(
+            parse_position("not a fen", &[]),
+            Err(PositionError::InvalidFen("not a fen".to_owned()))
+        );
+    }
+
+    #[test]
+    fn invalid_move() {
+        assert_eq!(
+            parse_position(CASTLING, &["e1"]),
+            Err(PositionError::InvalidMove("e1".to_owned()))
+        );
+    }
+
+    #[test]
+    fn illegal_move() {
+        assert_eq!(
+            parse_position(CASTLING, &["e1e3"]),
+            Err(PositionError::IllegalMove("e1e3".to_owned()))
+        );
+        // no castling rights, so this is just an illegal king move
+        assert_eq!(
+            parse_position("r3k2r/8/8/8/8/8/8/R3K2R w - - 0 1", &["e1g1"]),
+            Err(PositionError::IllegalMove("e1g1".to_owned()))
+        );
+    }
+
+    #[test]
+    fn castling() {
+        let board = parse_position(CASTLING, &["e1g1", "e8c8"]).unwrap();
+        assert_eq!(board.king(Color::White), Square::G1);
+        assert_eq!(board.piece_on(Square::F1), Some(Piece::Rook));
+        assert_eq!(board.king(Color::Black), Square::C8);
+        assert_eq!(board.piece_on(Square::D8), Some(Piece::Rook));
+    }
+
+    #[test]
+    fn castling_round_trip() {
+        let board: Board = CASTLING.parse().unwrap();
+        for (uci, internal) in [("e1g1", "e1h1"), ("e1c1", "e1a1")] {
+            let uci: Move = uci.parse().unwrap();
+            let internal: Move = internal.parse().unwrap();
+            assert_eq!(from_uci_castling(&board, uci, false), internal);
+            assert_eq!(to_uci_castling(&board, internal, false), uci);
+            assert_eq!(from_uci_castling(&board, internal, true), internal);
+            assert_eq!(to_uci_castling(&board, internal, true), internal);
+        }
+    }
+
+    #[test]
+    fn king_moves_are_not_castling() {
+        // the king is off the e-file, so two squares sideways is never castling
+        let board: Board = "4k3/8/8/8/8/8/8/3K3R w - - 0 1".parse().unwrap();
+        let mv: Move = "d1f1".parse().unwrap();
+        assert_eq!(from_uci_castling(&board, mv, false), mv);
+
+        // without castling rights, a king on e1 moving to g1 is just a king move
+        let board: Board = "4k3/8/8/8/8/8/8/4K2R w - - 0 1".parse().unwrap();
+        let mv: Move = "e1g1".parse().unwrap();
+        assert_eq!(from_uci_castling(&board, mv, false), mv);
+    }
+}
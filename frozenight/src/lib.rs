@@ -1,28 +1,38 @@
-use std::collections::HashMap;
 use std::ops::ControlFlow;
 use std::sync::atomic::{AtomicBool, AtomicI16, AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use cozy_chess::{Board, Move};
 
+mod book;
+mod epd;
 mod eval;
+mod fen;
 mod nnue;
 mod position;
+mod san;
 mod search;
 mod threading;
 mod time;
 mod tt;
 
-pub use eval::Eval;
+pub use book::{polyglot_key, Book, BookSelection};
+pub use epd::{parse_epd, Epd, EpdResult};
+pub use eval::{Eval, EvalTrace};
+pub use fen::{board_to_fen, from_uci_castling, parse_position, to_uci_castling, PositionError};
+pub use san::{from_san, to_san, SanError};
 pub use threading::MtFrozenight;
 pub use time::TimeConstraint;
 
+#[cfg(feature = "syzygy")]
+pub use cozy_syzygy::Tablebase;
+
 use search::{AbdadaTable, PrivateState, Searcher, INVALID_MOVE};
 use time::TimeManager;
 use tt::TranspositionTable;
 
-pub use search::all_parameters;
+pub use search::{all_parameters, Pruning};
 
 pub struct Frozenight {
     board: Board,
@@ -30,16 +40,64 @@ pub struct Frozenight {
     shared_state: Arc<RwLock<SharedState>>,
     stats: Arc<Statistics>,
     state: PrivateState,
+    multipv: usize,
+    contempt: i16,
+    pruning: Pruning,
+    repetition_count: u8,
+    searchmoves: Vec<Move>,
+    currmove: Option<CurrmoveHandler>,
 }
 
+type CurrmoveHandler = Arc<dyn Fn(Move, usize) + Send + Sync>;
+
 #[derive(Clone, Debug)]
 pub struct SearchInfo {
     pub eval: Eval,
     pub nodes: u64,
     pub depth: i16,
     pub selective_depth: i16,
+    /// Permille of the transposition table used by this search.
+    pub hashfull: u16,
     pub best_move: Move,
     pub pv: Vec<Move>,
+    /// The evals and principal variations of the next best root moves when using MultiPV.
+    pub other_pvs: Vec<(Eval, Vec<Move>)>,
+    /// The nodes spent on each root move's subtree so far, by the thread reporting this info.
+    pub root_node_counts: Vec<(Move, u64)>,
+    /// Set if this is a partial result from an aspiration search which failed, in which case
+    /// `eval` is only a bound and the iteration is still in progress.
+    pub bound: Option<Bound>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Bound {
+    /// The eval is at least this good.
+    Lower,
+    /// The eval is at most this good.
+    Upper,
+}
+
+impl SearchInfo {
+    /// The info reported before the search completes any iteration. If the root has no legal
+    /// moves, this is all there will be: no best move, and a mated or drawn eval.
+    fn initial(board: &Board) -> Self {
+        let mated = !board.checkers().is_empty() && !board.generate_moves(|_| true);
+        SearchInfo {
+            eval: match mated {
+                true => -Eval::MATE,
+                false => Eval::DRAW,
+            },
+            nodes: 0,
+            depth: 0,
+            selective_depth: 0,
+            hashfull: 0,
+            best_move: INVALID_MOVE,
+            pv: vec![],
+            other_pvs: vec![],
+            root_node_counts: vec![],
+            bound: None,
+        }
+    }
 }
 
 #[derive(Debug, Default)]
@@ -51,6 +109,8 @@ struct Statistics {
 struct SharedState {
     tt: TranspositionTable,
     abdada: AbdadaTable,
+    #[cfg(feature = "syzygy")]
+    tb: Tablebase,
 }
 
 impl Frozenight {
@@ -58,6 +118,8 @@ impl Frozenight {
         Self::create(Arc::new(RwLock::new(SharedState {
             tt: TranspositionTable::new(hash_mb),
             abdada: AbdadaTable::new(),
+            #[cfg(feature = "syzygy")]
+            tb: Tablebase::new(),
         })))
     }
 
@@ -68,6 +130,12 @@ impl Frozenight {
             shared_state,
             stats: Default::default(),
             state: Default::default(),
+            multipv: 1,
+            contempt: 0,
+            pruning: Pruning::ALL,
+            repetition_count: 2,
+            searchmoves: vec![],
+            currmove: None,
         }
     }
 
@@ -107,35 +175,86 @@ impl Frozenight {
         shared.tt = TranspositionTable::new(hash_mb);
     }
 
+    #[cfg(feature = "syzygy")]
+    pub fn set_tablebase(&mut self, tb: Tablebase) {
+        Arc::get_mut(&mut self.shared_state)
+            .unwrap()
+            .get_mut()
+            .unwrap()
+            .tb = tb;
+    }
+
+    pub fn set_multipv(&mut self, multipv: usize) {
+        self.multipv = multipv;
+    }
+
+    /// Sets how many centipawns worse than even the engine considers a draw.
+    pub fn set_contempt(&mut self, contempt: i16) {
+        self.contempt = contempt;
+    }
+
+    /// Sets which pruning heuristics the search may use.
+    pub fn set_pruning(&mut self, pruning: Pruning) {
+        self.pruning = pruning;
+    }
+
+    /// Sets how many times a position must occur for the search to score it as a draw. Positions
+    /// from before the root always need to occur three times, since that is what the rules
+    /// require; with the default of 2, a position repeated inside the search is a draw already.
+    pub fn set_repetition_count(&mut self, count: u8) {
+        self.repetition_count = count;
+    }
+
+    /// Restricts the next searches to the given root moves of the current position. An empty list
+    /// searches all moves. Duplicates and illegal moves are dropped, but it is an error if none of
+    /// the moves are legal.
+    pub fn set_searchmoves(&mut self, moves: Vec<Move>) -> Result<(), PositionError> {
+        let legal = legal_searchmoves(&self.board, &moves);
+        self.searchmoves = legal.clone().unwrap_or_default();
+        legal.map(drop)
+    }
+
+    /// Sets a function to call with each root move and its number as the search starts on it.
+    pub fn set_currmove_handler(&mut self, f: impl Fn(Move, usize) + Send + Sync + 'static) {
+        self.currmove = Some(Arc::new(f));
+    }
+
     pub fn search(
         &mut self,
         time: TimeConstraint,
         mut info: impl FnMut(&SearchInfo),
     ) -> SearchInfo {
-        let mut recent_info = SearchInfo {
-            eval: Eval::DRAW,
-            nodes: 0,
-            depth: 0,
-            selective_depth: 0,
-            best_move: INVALID_MOVE,
-            pv: vec![],
-        };
+        let mut recent_info = SearchInfo::initial(&self.board);
         let mut tm = TimeManager::new(&self.board, time);
         self.search_internal(
             time.depth,
             time.nodes,
+            self.multipv,
             &Default::default(),
             false,
             tm.deadline(),
-            |depth, searcher, best_move, eval| {
-                recent_info = SearchInfo {
+            |depth, searcher, lines, bound| {
+                let (best_move, eval) = lines[0];
+                let new_info = SearchInfo {
                     eval,
                     depth,
                     selective_depth: searcher.stats.selective_depth.load(Ordering::Relaxed),
                     nodes: searcher.stats.nodes.load(Ordering::Relaxed),
+                    hashfull: searcher.shared.tt.hashfull(),
                     best_move,
-                    pv: searcher.extract_pv(depth),
+                    pv: searcher.extract_pv(best_move, depth),
+                    other_pvs: lines[1..]
+                        .iter()
+                        .map(|&(mv, eval)| (eval, searcher.extract_pv(mv, depth)))
+                        .collect(),
+                    root_node_counts: searcher.root_node_totals.clone(),
+                    bound,
                 };
+                if bound.is_some() {
+                    info(&new_info);
+                    return ControlFlow::Continue(());
+                }
+                recent_info = new_info;
                 info(&recent_info);
 
                 tm.update(&recent_info)
@@ -144,36 +263,114 @@ impl Frozenight {
         recent_info
     }
 
+    /// Searches the position reached by playing `moves` from `start` for about `time` and returns
+    /// the best move and its eval, or `None` if there are no legal moves. Passing the whole game
+    /// lets repetitions be detected; the TT and move ordering state are kept between calls, so use
+    /// `new_game` when switching to another game.
+    pub fn best_move(
+        &mut self,
+        start: &Board,
+        moves: &[Move],
+        time: Duration,
+    ) -> Option<(Move, Eval)> {
+        self.set_position(start.clone(), moves.iter().copied());
+        let info = self.search(
+            TimeConstraint {
+                clock: Some(time),
+                ..TimeConstraint::INFINITE
+            },
+            |_| {},
+        );
+        match info.best_move == INVALID_MOVE {
+            true => None,
+            false => Some((info.best_move, info.eval)),
+        }
+    }
+
     fn search_internal(
         &mut self,
         max_depth: i16,
         max_nodes: u64,
+        multipv: usize,
         abort: &AtomicBool,
         multithreaded: bool,
         deadline: Option<Instant>,
-        mut depth_complete: impl FnMut(i16, &mut Searcher, Move, Eval) -> ControlFlow<()>,
+        mut depth_complete: impl FnMut(
+            i16,
+            &mut Searcher,
+            &[(Move, Eval)],
+            Option<Bound>,
+        ) -> ControlFlow<()>,
     ) {
         self.stats.clear();
 
+        let board = &self.board;
+        let restricted = !self.searchmoves.is_empty();
+        self.searchmoves.retain(|&mv| board.is_legal(mv));
+        let mut root_moves = 0;
+        self.board.generate_moves(|mvs| {
+            root_moves += mvs.len();
+            false
+        });
+        if root_moves == 0 {
+            // checkmate or stalemate; there is nothing to search
+            return;
+        }
+        if restricted && self.searchmoves.is_empty() {
+            // the position changed and none of the searchmoves are legal any more
+            return;
+        }
+        if !self.searchmoves.is_empty() {
+            root_moves = self.searchmoves.len();
+        }
+        let multipv = multipv.clamp(1, root_moves.max(1));
+
         self.with_searcher(max_nodes, multithreaded, abort, deadline, |mut searcher| {
-            let mut prev_eval = Eval::DRAW;
+            let multipv = match searcher.filter_tb_root_moves() {
+                Some(moves) => multipv.min(moves),
+                None => multipv,
+            };
+            let mut lines: Vec<(Move, Eval)> = vec![];
 
-            for depth in 1..=max_depth {
-                let (eval, mv) = match searcher.search(depth, prev_eval) {
-                    Some(v) => v,
-                    None => break,
-                };
+            'deepen: for depth in 1..=max_depth {
+                let prev_lines = std::mem::take(&mut lines);
+                for i in 0..multipv {
+                    let around = prev_lines.get(i).map_or(Eval::DRAW, |&(_, eval)| eval);
+                    let seed = prev_lines.get(i).map(|&(mv, _)| mv);
+                    // report the first line's aspiration failures as they happen
+                    let result =
+                        searcher.search(depth, around, seed, &mut |searcher, eval, mv, bound| {
+                            if i == 0 {
+                                let _ = depth_complete(depth, searcher, &[(mv, eval)], Some(bound));
+                            }
+                        });
+                    let (eval, mv) = match result {
+                        Some(v) => v,
+                        None => break 'deepen,
+                    };
+                    lines.push((mv, eval));
+                    searcher.root_excluded.push(mv);
+                }
+                searcher.root_excluded.clear();
+                // a later line can still come out ahead of an earlier one it was searched without
+                lines.sort_by_key(|&(_, eval)| std::cmp::Reverse(eval));
 
-                if depth_complete(depth, &mut searcher, mv, eval).is_break() {
+                if depth_complete(depth, &mut searcher, &lines, None).is_break() {
                     break;
                 }
-
-                prev_eval = eval;
             }
         })
     }
 }
 
+/// Evaluates `board` statically, from white's perspective.
+pub fn trace_eval(board: &Board) -> EvalTrace {
+    let mut trace = position::Position::from_root(board.clone()).trace_eval();
+    trace.network = trace.network.white_relative(board.side_to_move());
+    trace.eval = trace.eval.white_relative(board.side_to_move());
+    trace
+}
+
 impl Statistics {
     fn clear(&self) {
         self.selective_depth.store(0, Ordering::Relaxed);
@@ -181,6 +378,20 @@ impl Statistics {
     }
 }
 
+/// The distinct legal moves of `moves`, or an error if it names moves but none of them are legal.
+fn legal_searchmoves(board: &Board, moves: &[Move]) -> Result<Vec<Move>, PositionError> {
+    let mut legal = vec![];
+    for &mv in moves {
+        if board.is_legal(mv) && !legal.contains(&mv) {
+            legal.push(mv);
+        }
+    }
+    match moves.first() {
+        Some(mv) if legal.is_empty() => Err(PositionError::IllegalMove(mv.to_string())),
+        _ => Ok(legal),
+    }
+}
+
 fn update_position(
     board: &mut Board,
     prehistory: &mut Vec<u64>,
@@ -191,27 +402,20 @@ fn update_position(
     if board.same_position(old) {
         moves_since_last = 0;
     }
-    let mut occurances = HashMap::<_, i32>::new();
+    prehistory.clear();
 
     for mv in moves {
         moves_since_last += 1;
-        *occurances.entry(board.hash()).or_default() += 1;
+        prehistory.push(board.hash());
         board.play(mv);
         if board.halfmove_clock() == 0 {
-            occurances.clear();
+            prehistory.clear();
         }
         if board.same_position(old) {
             moves_since_last = 0;
         }
     }
 
-    prehistory.clear();
-    prehistory.extend(
-        occurances
-            .into_iter()
-            .filter(|&(_, c)| c > 1)
-            .map(|(h, _)| h),
-    );
     prehistory.push(board.hash());
 
     match moves_since_last {
@@ -220,3 +424,318 @@ fn update_position(
         _ => 2,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(fen: &str) -> SearchInfo {
+        let mut engine = Frozenight::new(1);
+        engine.set_position(fen.parse().unwrap(), std::iter::empty());
+        let time = TimeConstraint {
+            depth: 4,
+            ..TimeConstraint::INFINITE
+        };
+        engine.search(time, |_| {
+            panic!("no iteration should complete without root moves")
+        })
+    }
+
+    fn search_to_depth(fen: &str, depth: i16) -> SearchInfo {
+        search_with_pruning(fen, depth, Pruning::ALL)
+    }
+
+    fn search_with_pruning(fen: &str, depth: i16, pruning: Pruning) -> SearchInfo {
+        let mut engine = Frozenight::new(1);
+        engine.set_pruning(pruning);
+        engine.set_position(fen.parse().unwrap(), std::iter::empty());
+        let time = TimeConstraint {
+            depth,
+            ..TimeConstraint::INFINITE
+        };
+        engine.search(time, |_| {})
+    }
+
+    #[test]
+    fn mate_in_three() {
+        let info = search_to_depth("4k3/8/8/8/8/8/1R6/2R3K1 w - - 0 1", 8);
+        assert_eq!(info.eval, Eval::MATE.add_time(5));
+        assert_eq!(info.eval.to_string(), "mate 3");
+        assert!(["b2b7", "c1c7"].contains(&info.best_move.to_string().as_str()));
+    }
+
+    #[test]
+    fn lmr_keeps_best_moves() {
+        let mut without = Pruning::ALL;
+        without.set(Pruning::LMR, false);
+
+        let suite: [(&str, &[&str]); 4] = [
+            ("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", &["a1a8"]),
+            ("5k2/8/8/8/8/8/8/1RR3K1 w - - 0 1", &["b1b7", "c1c7"]),
+            ("4k3/8/8/8/8/8/1R6/2R3K1 w - - 0 1", &["b2b7", "c1c7"]),
+            ("4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1", &["d1d5"]),
+        ];
+        for (fen, best_moves) in suite {
+            for pruning in [Pruning::ALL, without] {
+                let info = search_with_pruning(fen, 8, pruning);
+                let best_move = info.best_move.to_string();
+                assert!(
+                    best_moves.contains(&best_move.as_str()),
+                    "{} in {}",
+                    best_move,
+                    fen
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn multi_cut_keeps_tactics() {
+        let mut without = Pruning::ALL;
+        without.set(Pruning::MULTI_CUT, false);
+
+        for fen in [
+            "4k3/8/8/8/8/8/1R6/2R3K1 w - - 0 1",
+            "4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1",
+        ] {
+            let with = search_with_pruning(fen, 10, Pruning::ALL);
+            let without = search_with_pruning(fen, 10, without);
+            if with.eval.is_conclusive() || without.eval.is_conclusive() {
+                assert_eq!(with.eval, without.eval, "{}", fen);
+            }
+            assert_eq!(with.best_move, without.best_move, "{}", fen);
+            // multi-cut should save nodes, give or take some noise from the changed TT contents
+            assert!(with.nodes <= without.nodes * 11 / 10, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn probcut_keeps_tactics() {
+        let mut without = Pruning::ALL;
+        without.set(Pruning::PROBCUT, false);
+
+        for fen in [
+            "4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1",
+            "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+        ] {
+            let with = search_with_pruning(fen, 8, Pruning::ALL);
+            let without = search_with_pruning(fen, 8, without);
+            if with.eval.is_conclusive() || without.eval.is_conclusive() {
+                assert_eq!(with.eval, without.eval, "{}", fen);
+            }
+            assert_eq!(with.best_move, without.best_move, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn fifty_move_draw() {
+        // every line reaches the fifty-move limit long before white could mate
+        let info = search_to_depth("8/8/8/4k3/8/8/8/R3K3 w - - 98 80", 4);
+        assert!(info.eval.raw().abs() <= 5, "{}", info.eval);
+    }
+
+    #[test]
+    fn mate_beats_fifty_move_rule() {
+        // the mate is delivered on the move which reaches the limit
+        let info = search_to_depth("6k1/5ppp/8/8/8/8/8/R5K1 w - - 99 80", 4);
+        assert_eq!(info.eval, Eval::MATE.add_time(1));
+        assert_eq!(info.best_move, "a1a8".parse().unwrap());
+    }
+
+    #[test]
+    fn duplicate_searchmoves() {
+        let mut engine = Frozenight::new(1);
+        let e4: Move = "e2e4".parse().unwrap();
+        engine.set_searchmoves(vec![e4, e4]).unwrap();
+        engine.set_multipv(2);
+        let time = TimeConstraint {
+            depth: 4,
+            ..TimeConstraint::INFINITE
+        };
+        let info = engine.search(time, |_| {});
+        assert_eq!(info.best_move, e4);
+        assert_eq!(info.pv.first(), Some(&e4));
+        assert!(info.other_pvs.is_empty());
+    }
+
+    #[test]
+    fn multipv_reports_both_captures() {
+        // either rook can take a loose knight, and nothing else comes close
+        let mut engine = Frozenight::new(1);
+        engine.set_position(
+            "4k3/8/8/2n2n2/8/8/K7/2R2R2 w - - 0 1".parse().unwrap(),
+            std::iter::empty(),
+        );
+        engine.set_multipv(3);
+        let time = TimeConstraint {
+            depth: 6,
+            ..TimeConstraint::INFINITE
+        };
+        let info = engine.search(time, |_| {});
+        assert_eq!(info.other_pvs.len(), 2);
+
+        let mut captures = vec![info.best_move, info.other_pvs[0].1[0]];
+        captures.sort_by_key(|mv| mv.to_string());
+        let expected: Vec<Move> = vec!["c1c5".parse().unwrap(), "f1f5".parse().unwrap()];
+        assert_eq!(captures, expected);
+
+        let mut evals = vec![info.eval];
+        evals.extend(info.other_pvs.iter().map(|&(eval, _)| eval));
+        assert!(evals.windows(2).all(|w| w[0] >= w[1]), "{:?}", evals);
+    }
+
+    #[test]
+    fn root_node_counts_cover_the_search() {
+        let mut engine = Frozenight::new(1);
+        let time = TimeConstraint {
+            depth: 7,
+            ..TimeConstraint::INFINITE
+        };
+        let mut reports = 0;
+        engine.search(time, |info| {
+            // the root itself is never counted as a node, so only the subtrees make up the total
+            let counted: u64 = info.root_node_counts.iter().map(|&(_, nodes)| nodes).sum();
+            assert_eq!(counted, info.nodes, "depth {}", info.depth);
+            reports += 1;
+        });
+        assert!(reports > 0);
+    }
+
+    #[test]
+    fn illegal_searchmoves() {
+        let mut engine = Frozenight::new(1);
+        let e5: Move = "e2e5".parse().unwrap();
+        assert_eq!(
+            engine.set_searchmoves(vec![e5]),
+            Err(PositionError::IllegalMove("e2e5".to_owned()))
+        );
+
+        // the illegal move is dropped rather than widening the search to every move
+        let e4: Move = "e2e4".parse().unwrap();
+        engine.set_searchmoves(vec![e5, e4]).unwrap();
+        let time = TimeConstraint {
+            depth: 2,
+            ..TimeConstraint::INFINITE
+        };
+        assert_eq!(engine.search(time, |_| {}).best_move, e4);
+    }
+
+    #[test]
+    fn checkmated_root() {
+        let info = search("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+        assert_eq!(info.eval, -Eval::MATE);
+        assert_eq!(info.eval.to_string(), "mate 0");
+        assert_eq!(info.best_move, INVALID_MOVE);
+        assert!(info.pv.is_empty());
+    }
+
+    #[test]
+    fn stalemated_root() {
+        let info = search("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert_eq!(info.eval, Eval::DRAW);
+        assert_eq!(info.eval.to_string(), "cp 0");
+        assert_eq!(info.best_move, INVALID_MOVE);
+        assert!(info.pv.is_empty());
+    }
+
+    #[test]
+    fn best_move_follows_the_game() {
+        let mut engine = Frozenight::new(1);
+        let start = Board::default();
+        let time = Duration::from_millis(50);
+        let mut moves: Vec<Move> = vec![];
+        for _ in 0..2 {
+            let mut board = start.clone();
+            for &mv in &moves {
+                board.play(mv);
+            }
+            let (mv, eval) = engine.best_move(&start, &moves, time).unwrap();
+            assert!(board.is_legal(mv), "{} in {}", mv, board);
+            assert!(!eval.is_conclusive());
+            moves.push(mv);
+        }
+
+        let mated: Board = "R5k1/5ppp/8/8/8/8/8/6K1 b - - 1 1".parse().unwrap();
+        assert_eq!(engine.best_move(&mated, &[], time), None);
+    }
+
+    #[test]
+    fn depth_limit() {
+        let mut engine = Frozenight::new(1);
+        let time = TimeConstraint {
+            depth: 5,
+            ..TimeConstraint::INFINITE
+        };
+        let info = engine.search(time, |info| assert!(info.depth <= 5));
+        assert_eq!(info.depth, 5);
+    }
+
+    #[test]
+    fn node_limit() {
+        let mut engine = Frozenight::new(1);
+        let time = TimeConstraint {
+            nodes: 20_000,
+            ..TimeConstraint::INFINITE
+        };
+        let info = engine.search(time, |_| {});
+        // the search stops at the first full-depth node past the limit, give or take a qsearch
+        let nodes = engine.stats.nodes.load(Ordering::Relaxed);
+        assert!((20_000..21_000).contains(&nodes), "{}", nodes);
+        assert!(info.nodes <= nodes);
+    }
+
+    #[test]
+    fn move_time() {
+        let mut engine = Frozenight::new(1);
+        let movetime = Duration::from_millis(200);
+        let time = TimeConstraint {
+            clock: Some(movetime),
+            use_all_time: true,
+            ..TimeConstraint::INFINITE
+        };
+        let start = Instant::now();
+        engine.search(time, |_| {});
+        // all of the time is used, and no more
+        let elapsed = start.elapsed();
+        assert!(elapsed >= movetime, "{:?}", elapsed);
+        assert!(elapsed < movetime * 3 / 2, "{:?}", elapsed);
+    }
+
+    /// Needs the KQvK tables in the directory named by `SYZYGY_PATH`, and passes trivially
+    /// without it.
+    #[cfg(feature = "syzygy")]
+    #[test]
+    fn tb_root_moves_keep_the_win() {
+        let path = match std::env::var("SYZYGY_PATH") {
+            Ok(path) => path,
+            Err(_) => return,
+        };
+        let mut tb = Tablebase::new();
+        tb.add_directory(&path).unwrap();
+
+        let mut engine = Frozenight::new(1);
+        engine.set_tablebase(tb);
+        // as many lines as there are moves, to see every root move the filter keeps
+        engine.set_multipv(64);
+        engine.set_position(
+            "8/8/8/8/8/2k5/7Q/4K3 w - - 0 1".parse().unwrap(),
+            std::iter::empty(),
+        );
+        let time = TimeConstraint {
+            depth: 4,
+            ..TimeConstraint::INFINITE
+        };
+        let info = engine.search(time, |_| {});
+
+        let mut kept = vec![info.best_move];
+        kept.extend(info.other_pvs.iter().map(|(_, pv)| pv[0]));
+        // the black king takes the queen on c2 or b2, where the white king doesn't guard it
+        for hanging in ["h2c2", "h2b2"] {
+            assert!(!kept.contains(&hanging.parse().unwrap()), "{}", hanging);
+        }
+        assert!(info.eval > Eval::DRAW);
+        for (eval, _) in &info.other_pvs {
+            assert!(*eval > Eval::DRAW);
+        }
+    }
+}
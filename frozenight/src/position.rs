@@ -1,12 +1,16 @@
-use cozy_chess::{Board, Move};
+use cozy_chess::{BitBoard, Board, Color, Move, Piece, Square};
 
 use crate::nnue::NnueAccumulator;
-use crate::Eval;
+use crate::{Eval, EvalTrace};
 
 #[derive(Clone)]
 pub struct Position {
     pub board: Board,
     pub ply: u16,
+    /// The piece moved and its destination square for the move leading to this position.
+    pub last_move: Option<(Piece, Square)>,
+    /// The same for the move before `last_move`.
+    pub second_last_move: Option<(Piece, Square)>,
     nnue: NnueAccumulator,
 }
 
@@ -16,6 +20,8 @@ impl Position {
             nnue: NnueAccumulator::new(&board),
             board,
             ply: 0,
+            last_move: None,
+            second_last_move: None,
         }
     }
 
@@ -26,6 +32,8 @@ impl Position {
             board,
             nnue: self.nnue.play_move(&self.board, mv),
             ply: self.ply + 1,
+            last_move: self.board.piece_on(mv.from).map(|piece| (piece, mv.to)),
+            second_last_move: self.last_move,
         }
     }
 
@@ -34,14 +42,152 @@ impl Position {
             board: self.board.null_move()?,
             nnue: self.nnue,
             ply: self.ply + 1,
+            last_move: None,
+            second_last_move: self.last_move,
         })
     }
 
     pub fn static_eval(&self) -> Eval {
-        self.nnue.calculate(self.board.side_to_move())
+        self.trace_eval().eval
+    }
+
+    /// Computes the static eval along with the steps leading to it, from the side to move's
+    /// perspective.
+    pub fn trace_eval(&self) -> EvalTrace {
+        let network = self.nnue.calculate(self.board.side_to_move());
+
+        // scale towards a draw as the fifty-move rule approaches
+        let halfmoves = self.board.halfmove_clock().min(100) as i32;
+        let fifty_move_scale = 100 - halfmoves;
+        let eval = network.raw() as i32 * fifty_move_scale / 100;
+
+        let endgame_scale = scale_factor(&self.board);
+        EvalTrace {
+            network,
+            fifty_move_scale,
+            endgame_scale,
+            eval: Eval::new((eval * endgame_scale / 64) as i16),
+        }
     }
 
     pub fn is_capture(&self, mv: Move) -> bool {
-        self.board.colors(!self.board.side_to_move()).has(mv.to)
+        self.capture_victim(mv).is_some()
+    }
+
+    pub fn capture_victim(&self, mv: Move) -> Option<Piece> {
+        capture_victim(&self.board, mv)
+    }
+}
+
+/// The piece `mv` captures, if any. Castling, encoded as the king capturing its own rook, is not
+/// a capture.
+pub fn capture_victim(board: &Board, mv: Move) -> Option<Piece> {
+    if board.colors(!board.side_to_move()).has(mv.to) {
+        return board.piece_on(mv.to);
+    }
+    // pawns only change file when capturing, so this is en passant
+    match board.piece_on(mv.from) == Some(Piece::Pawn) && mv.from.file() != mv.to.file() {
+        true => Some(Piece::Pawn),
+        false => None,
+    }
+}
+
+/// How much of the eval to keep, out of 64. Used to recognize drawish endgames.
+fn scale_factor(board: &Board) -> i32 {
+    let bishops = board.pieces(Piece::Bishop);
+    let others =
+        board.pieces(Piece::Knight) | board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+    let white = bishops & board.colors(Color::White);
+    let black = bishops & board.colors(Color::Black);
+    if !others.is_empty() || white.len() != 1 || black.len() != 1 {
+        return 64;
+    }
+
+    let square_color = |bb: BitBoard| {
+        bb.next_square()
+            .map(|sq| (sq.file() as usize + sq.rank() as usize) % 2)
+    };
+    if square_color(white) == square_color(black) {
+        return 64;
+    }
+
+    // opposite colored bishop endgames are drawish, the more so the fewer pawns are left
+    let pawns = board.pieces(Piece::Pawn).len() as i32;
+    (16 + 4 * pawns).min(48)
+}
+
+#[cfg(test)]
+mod tests {
+    use cozy_chess::{Board, Piece};
+
+    use super::{capture_victim, scale_factor};
+
+    fn victim(fen: &str, mv: &str) -> Option<Piece> {
+        let board: Board = fen.parse().unwrap();
+        capture_victim(&board, mv.parse().unwrap())
+    }
+
+    #[test]
+    fn captures() {
+        assert_eq!(
+            victim("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5"),
+            Some(Piece::Pawn)
+        );
+        assert_eq!(
+            victim("4k3/8/8/3q4/8/4N3/8/4K3 w - - 0 1", "e3d5"),
+            Some(Piece::Queen)
+        );
+    }
+
+    #[test]
+    fn en_passant() {
+        // the destination square is empty, but the pawn beside it is captured
+        assert_eq!(
+            victim("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"),
+            Some(Piece::Pawn)
+        );
+    }
+
+    #[test]
+    fn promotions() {
+        let fen = "3r3k/4P3/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(victim(fen, "e7d8q"), Some(Piece::Rook));
+        assert_eq!(victim(fen, "e7e8q"), None);
+    }
+
+    #[test]
+    fn quiets() {
+        assert_eq!(victim("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", "e2e4"), None);
+        assert_eq!(victim("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", "e1d1"), None);
+    }
+
+    #[test]
+    fn castling() {
+        // encoded as the king capturing its own rook
+        assert_eq!(victim("4k3/8/8/8/8/8/8/4K2R w K - 0 1", "e1h1"), None);
+    }
+
+    fn scale(fen: &str) -> i32 {
+        scale_factor(&fen.parse().unwrap())
+    }
+
+    #[test]
+    fn opposite_colored_bishops() {
+        // dark-squared c1 bishop against light-squared c8 bishop, a pawn up
+        assert_eq!(scale("2b1k3/8/8/8/8/8/4P3/2B1K3 w - - 0 1"), 20);
+        assert_eq!(scale("2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1"), 16);
+        // lots of pawns keep more of the eval, up to a cap
+        assert_eq!(scale("2b1k3/pppppppp/8/8/8/8/PPPPPPPP/2B1K3 w - - 0 1"), 48);
+    }
+
+    #[test]
+    fn not_opposite_colored_bishops() {
+        // same-colored bishops
+        assert_eq!(scale("4kb2/8/8/8/8/8/4P3/2B1K3 w - - 0 1"), 64);
+        // other pieces on the board
+        assert_eq!(scale("2b1k3/8/8/8/8/8/4P3/2B1K1N1 w - - 0 1"), 64);
+        assert_eq!(scale("2b1k2r/8/8/8/8/8/4P3/2B1K2R w - - 0 1"), 64);
+        // a second bishop
+        assert_eq!(scale("2b1k3/8/8/8/8/8/4P3/2B1KB2 w - - 0 1"), 64);
     }
 }
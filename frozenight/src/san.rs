@@ -0,0 +1,196 @@
+use cozy_chess::{Board, Move, Piece};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SanError(pub String);
+
+impl std::fmt::Display for SanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no legal move matches SAN: {}", self.0)
+    }
+}
+
+impl std::error::Error for SanError {}
+
+/// Writes a legal move in standard algebraic notation, including disambiguation and check or
+/// mate suffixes.
+///
+/// ```
+/// use cozy_chess::Board;
+/// use frozenight::to_san;
+///
+/// let san = |fen: &str, mv: &str| to_san(&fen.parse().unwrap(), mv.parse().unwrap());
+///
+/// let startpos = Board::default();
+/// assert_eq!(to_san(&startpos, "e2e4".parse().unwrap()), "e4");
+/// assert_eq!(to_san(&startpos, "g1f3".parse().unwrap()), "Nf3");
+///
+/// // disambiguation by file, by rank, and by both
+/// assert_eq!(san("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1", "b1d2"), "Nbd2");
+/// assert_eq!(san("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3"), "R1a3");
+/// assert_eq!(san("4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1", "a1b2"), "Qa1b2");
+/// assert_eq!(san("4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1", "a3b2"), "Q3b2");
+///
+/// // the knight on c3 is pinned, so it can't go to e2 as well
+/// assert_eq!(san("4k3/8/8/b7/8/2N5/8/4K1N1 w - - 0 1", "g1e2"), "Ne2");
+///
+/// assert_eq!(san("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1h1"), "O-O");
+/// assert_eq!(san("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1a1"), "O-O-O");
+///
+/// assert_eq!(san("8/4P3/8/8/8/8/k7/4K3 w - - 0 1", "e7e8q"), "e8=Q");
+/// assert_eq!(san("3r4/4P3/8/8/8/8/k7/4K3 w - - 0 1", "e7d8n"), "exd8=N");
+///
+/// assert_eq!(san("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", "a1a8"), "Ra8+");
+/// assert_eq!(san("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8"), "Ra8#");
+/// ```
+pub fn to_san(board: &Board, mv: Move) -> String {
+    let mut san = san_without_suffix(board, mv);
+
+    let mut after = board.clone();
+    after.play_unchecked(mv);
+    if !after.checkers().is_empty() {
+        match after.generate_moves(|_| true) {
+            true => san.push('+'),
+            false => san.push('#'),
+        }
+    }
+    san
+}
+
+/// Parses a legal move in standard algebraic notation. Check, mate and annotation suffixes are
+/// optional, as is the `=` before a promotion piece.
+///
+/// ```
+/// use cozy_chess::Board;
+/// use frozenight::from_san;
+///
+/// let startpos = Board::default();
+/// assert_eq!(from_san(&startpos, "Nf3"), Ok("g1f3".parse().unwrap()));
+/// assert!(from_san(&startpos, "Nf4").is_err());
+///
+/// let board: Board = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1".parse().unwrap();
+/// assert_eq!(from_san(&board, "0-0"), Ok("e1h1".parse().unwrap()));
+/// assert_eq!(from_san(&board, "O-O-O"), Ok("e1a1".parse().unwrap()));
+///
+/// let board: Board = "8/4P3/8/8/8/8/k7/4K3 w - - 0 1".parse().unwrap();
+/// assert_eq!(from_san(&board, "e8Q"), Ok("e7e8q".parse().unwrap()));
+/// assert_eq!(from_san(&board, "e8=Q+!?"), Ok("e7e8q".parse().unwrap()));
+///
+/// // ambiguous moves need disambiguating
+/// let board: Board = "4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1".parse().unwrap();
+/// assert!(from_san(&board, "Nd2").is_err());
+/// assert_eq!(from_san(&board, "Nfd2"), Ok("f1d2".parse().unwrap()));
+/// ```
+pub fn from_san(board: &Board, san: &str) -> Result<Move, SanError> {
+    let normalize = |s: &str| {
+        s.trim_end_matches(|c| matches!(c, '+' | '#' | '!' | '?'))
+            .replace('0', "O")
+            .replace('=', "")
+    };
+    let wanted = normalize(san);
+
+    let mut found = None;
+    board.generate_moves(|mvs| {
+        for mv in mvs {
+            if normalize(&san_without_suffix(board, mv)) == wanted {
+                found = Some(mv);
+                return true;
+            }
+        }
+        false
+    });
+    found.ok_or_else(|| SanError(san.to_owned()))
+}
+
+fn san_without_suffix(board: &Board, mv: Move) -> String {
+    let us = board.side_to_move();
+    let piece = board.piece_on(mv.from).unwrap();
+    let from = mv.from.to_string();
+    let to = mv.to.to_string();
+
+    // cozy-chess encodes castling as the king capturing its own rook
+    if piece == Piece::King && board.colors(us).has(mv.to) {
+        return match mv.to.file() > mv.from.file() {
+            true => "O-O".to_owned(),
+            false => "O-O-O".to_owned(),
+        };
+    }
+
+    let capture =
+        board.colors(!us).has(mv.to) || (piece == Piece::Pawn && mv.from.file() != mv.to.file());
+    let mut san = String::new();
+    if piece == Piece::Pawn {
+        if capture {
+            san += &from[..1];
+        }
+    } else {
+        san.push(piece_char(piece));
+
+        // disambiguate from other pieces of the same kind which can reach the same square
+        let mut others = vec![];
+        board.generate_moves_for(board.pieces(piece), |mvs| {
+            others.extend(
+                mvs.into_iter()
+                    .filter(|m| m.to == mv.to && m.from != mv.from),
+            );
+            false
+        });
+        if !others.is_empty() {
+            if others.iter().all(|m| m.from.file() != mv.from.file()) {
+                san += &from[..1];
+            } else if others.iter().all(|m| m.from.rank() != mv.from.rank()) {
+                san += &from[1..];
+            } else {
+                san += &from;
+            }
+        }
+    }
+
+    if capture {
+        san.push('x');
+    }
+    san += &to;
+    if let Some(promotion) = mv.promotion {
+        san.push('=');
+        san.push(piece_char(promotion));
+    }
+    san
+}
+
+fn piece_char(piece: Piece) -> char {
+    match piece {
+        Piece::Pawn => 'P',
+        Piece::Knight => 'N',
+        Piece::Bishop => 'B',
+        Piece::Rook => 'R',
+        Piece::Queen => 'Q',
+        Piece::King => 'K',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cozy_chess::Board;
+
+    use super::{from_san, to_san};
+
+    #[test]
+    fn round_trip() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            "4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1",
+            "4k3/8/8/b7/8/2N5/8/4K1N1 w - - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ] {
+            let board: Board = fen.parse().unwrap();
+            board.generate_moves(|mvs| {
+                for mv in mvs {
+                    let san = to_san(&board, mv);
+                    assert_eq!(from_san(&board, &san), Ok(mv), "{} in {}", san, fen);
+                }
+                false
+            });
+        }
+    }
+}
@@ -5,13 +5,34 @@ use cozy_chess::{Board, Move, Square};
 
 use crate::position::Position;
 use crate::tt::{NodeKind, TableEntry};
-use crate::{Eval, Frozenight, SharedState, Statistics};
+use crate::{Bound, Eval, Frozenight, SharedState, Statistics};
 
 pub use self::abdada::AbdadaTable;
-use self::ordering::{OrderingState, BREAK, CONTINUE};
+use self::ordering::{MoveBuffers, MoveStage, OrderingState, BREAK, CONTINUE};
 pub use self::params::all_parameters;
+use self::params::{draw_jitter, ASPIRATION_DELTA, ASPIRATION_RESEARCHES};
 use self::window::Window;
 
+/// With the `trace` feature, prints a search event to stderr as a line of `key=value` fields,
+/// starting with the event name and the node's ply, depth and window.
+macro_rules! trace {
+    ($event:literal, $position:expr, $depth:expr, $window:expr $(, $key:literal => $value:expr)*) => {
+        #[cfg(feature = "trace")]
+        {
+            eprint!(
+                "trace event={} ply={} depth={} alpha={} beta={}",
+                $event,
+                $position.ply,
+                $depth,
+                $window.lb().raw(),
+                $window.ub().raw(),
+            );
+            $(eprint!(" {}={}", $key, $value);)*
+            eprintln!();
+        }
+    };
+}
+
 mod abdada;
 mod null;
 mod oracle;
@@ -28,6 +49,51 @@ pub const INVALID_MOVE: Move = Move {
     promotion: None,
 };
 
+/// A set of pruning and reduction heuristics, so that they can be turned off when debugging.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Pruning(u16);
+
+impl Pruning {
+    pub const NULL_MOVE: Pruning = Pruning(1 << 0);
+    pub const LMR: Pruning = Pruning(1 << 1);
+    pub const FUTILITY: Pruning = Pruning(1 << 2);
+    pub const RFP: Pruning = Pruning(1 << 3);
+    pub const RAZORING: Pruning = Pruning(1 << 4);
+    pub const SEE: Pruning = Pruning(1 << 5);
+    pub const PROBCUT: Pruning = Pruning(1 << 6);
+    pub const HISTORY: Pruning = Pruning(1 << 7);
+    pub const DELTA: Pruning = Pruning(1 << 8);
+    pub const MULTI_CUT: Pruning = Pruning(1 << 9);
+
+    pub const ALL: Pruning = Pruning((1 << 10) - 1);
+    pub const NONE: Pruning = Pruning(0);
+
+    /// Each heuristic along with a name for it.
+    pub const FLAGS: [(&'static str, Pruning); 10] = [
+        ("NullMove", Pruning::NULL_MOVE),
+        ("LMR", Pruning::LMR),
+        ("Futility", Pruning::FUTILITY),
+        ("RFP", Pruning::RFP),
+        ("Razoring", Pruning::RAZORING),
+        ("SEE", Pruning::SEE),
+        ("ProbCut", Pruning::PROBCUT),
+        ("History", Pruning::HISTORY),
+        ("Delta", Pruning::DELTA),
+        ("MultiCut", Pruning::MULTI_CUT),
+    ];
+
+    pub fn contains(self, flags: Pruning) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+
+    pub fn set(&mut self, flags: Pruning, enabled: bool) {
+        match enabled {
+            true => self.0 |= flags.0,
+            false => self.0 &= !flags.0,
+        }
+    }
+}
+
 pub(crate) struct PrivateState {
     history: OrderingState,
 }
@@ -52,6 +118,30 @@ pub(crate) struct Searcher<'a> {
     deadline: Option<Instant>,
     next_deadline_check: u64,
     multithreaded: bool,
+    nmp_min_ply: u16,
+    contempt: i16,
+    pruning: Pruning,
+    /// Root moves already reported as better principal variations this iteration.
+    pub root_excluded: Vec<Move>,
+    /// If not empty, the only root moves to search.
+    root_filter: Vec<Move>,
+    currmove: Option<&'a (dyn Fn(Move, usize) + Send + Sync)>,
+    /// Root moves and the nodes their subtrees took when last searched, used for root ordering.
+    root_nodes: Vec<(Move, u64)>,
+    /// Root moves and the nodes spent on their subtrees over the whole search.
+    pub root_node_totals: Vec<(Move, u64)>,
+    /// Triangular PV table: the best line found so far at each ply of the current line.
+    pv_table: Vec<Vec<Move>>,
+    /// The principal variation of each root move from the last search which found it best.
+    root_pvs: Vec<Vec<Move>>,
+    /// Static evals of the nodes on the current line, or `None` for nodes in check.
+    static_evals: Vec<Option<Eval>>,
+    /// The position hash and move at each ply of the principal variation being re-walked.
+    pv_seed: Vec<(u64, Move)>,
+    /// Spare move lists for `visit_moves`.
+    move_buffers: Vec<MoveBuffers>,
+    /// How many earlier occurrences of a position make it a draw.
+    repetitions_needed: usize,
     rep_list: Vec<u64>,
     rep_table: [u8; 1024],
 }
@@ -66,8 +156,9 @@ impl Frozenight {
         f: impl FnOnce(Searcher) -> T,
     ) -> T {
         self.state.history.decay();
+        let rep_list = self.root_rep_list();
         let mut rep_table = [0; 1024];
-        for &b in &self.prehistory {
+        for &b in &rep_list {
             rep_table[b as usize % 1024] += 1;
         }
         let shared = self.shared_state.read().unwrap();
@@ -89,17 +180,57 @@ impl Frozenight {
             },
             valid: true,
             allow_abort: false,
-            rep_list: self.prehistory.clone(),
+            nmp_min_ply: 0,
+            contempt: self.contempt,
+            pruning: self.pruning,
+            root_excluded: vec![],
+            root_filter: self.searchmoves.clone(),
+            currmove: self.currmove.as_deref(),
+            root_nodes: vec![],
+            root_node_totals: vec![],
+            pv_table: vec![],
+            root_pvs: vec![],
+            static_evals: vec![],
+            pv_seed: vec![],
+            move_buffers: vec![],
+            repetitions_needed: self.repetition_count.max(2) as usize - 1,
+            rep_list,
         })
     }
+
+    /// The positions the search should consider as already having occurred. When a single
+    /// repetition counts as a draw, positions which occurred only once before the root are left
+    /// out, since repeating those once more is not a draw yet.
+    fn root_rep_list(&self) -> Vec<u64> {
+        let (&current, earlier) = match self.prehistory.split_last() {
+            Some(v) => v,
+            None => return vec![],
+        };
+        if self.repetition_count > 2 {
+            return self.prehistory.clone();
+        }
+        earlier
+            .iter()
+            .copied()
+            .filter(|&h| earlier.iter().filter(|&&b| b == h).count() > 1)
+            .chain(std::iter::once(current))
+            .collect()
+    }
 }
 
 impl<'a> Searcher<'a> {
-    /// Launch the search.
+    /// Launch the search. If `seed` is a root move with a known principal variation, that line is
+    /// searched first. `report` is called with the result of each failed aspiration search.
     ///
     /// Invariant: `self` is unchanged if this function returns `Some`. If it returns none, then
     /// calling this function again will result in a panic.
-    pub fn search(&mut self, depth: i16, around: Eval) -> Option<(Eval, Move)> {
+    pub fn search(
+        &mut self,
+        depth: i16,
+        around: Eval,
+        seed: Option<Move>,
+        report: &mut dyn FnMut(&mut Searcher, Eval, Move, Bound),
+    ) -> Option<(Eval, Move)> {
         assert!(depth > 0);
         self.allow_abort = depth > 1;
         if !self.valid {
@@ -110,21 +241,64 @@ impl<'a> Searcher<'a> {
             panic!("root position (FEN: {}) has no moves", self.root);
         }
 
-        let window = match () {
-            _ if depth < 3 => Window::default(),
-            _ if around.is_conclusive() => Window::default(),
-            _ => Window::new(around - 500, around + 500),
-        };
+        self.pv_seed.clear();
+        if let Some(line) = self.root_pvs.iter().find(|line| Some(line[0]) == seed) {
+            let mut board = self.root.clone();
+            for &mv in line {
+                if !board.is_legal(mv) {
+                    break;
+                }
+                self.pv_seed.push((board.hash(), mv));
+                board.play_unchecked(mv);
+            }
+        }
 
         let position = &Position::from_root(self.root.clone());
+        let (eval, mv) = self.aspiration_search(position, depth, around, report)?;
 
-        let (eval, mv) = self.pv_search(position, window, depth)?;
+        let pv = self.pv_table.first().cloned().unwrap_or_default();
+        if pv.first() == Some(&mv) {
+            self.root_pvs.retain(|line| line[0] != mv);
+            self.root_pvs.push(pv);
+        }
 
-        if window.fail_low(eval) || window.fail_high(eval) {
-            self.pv_search(position, Window::default(), depth)
-        } else {
-            Some((eval, mv))
+        Some((eval, mv))
+    }
+
+    fn aspiration_search(
+        &mut self,
+        position: &Position,
+        depth: i16,
+        around: Eval,
+        report: &mut dyn FnMut(&mut Searcher, Eval, Move, Bound),
+    ) -> Option<(Eval, Move)> {
+        if depth < 3 || around.is_conclusive() {
+            return self.pv_search(position, Window::default(), depth);
         }
+
+        // aspiration windows: widen whichever side failed, falling back to a full window
+        let mut delta = ASPIRATION_DELTA.get();
+        let mut lb = around - delta;
+        let mut ub = around + delta;
+        for _ in 0..ASPIRATION_RESEARCHES.get() {
+            let window = Window::new(lb, ub);
+            let (eval, mv) = self.pv_search(position, window, depth)?;
+
+            if eval.is_conclusive() {
+                break;
+            } else if window.fail_low(eval) {
+                report(self, eval, mv, Bound::Upper);
+                lb = lb - delta;
+            } else if window.fail_high(eval) {
+                report(self, eval, mv, Bound::Lower);
+                ub = ub + delta;
+            } else {
+                return Some((eval, mv));
+            }
+            delta = delta.saturating_mul(2);
+        }
+
+        self.pv_search(position, Window::default(), depth)
     }
 
     fn visit_node(
@@ -134,10 +308,13 @@ impl<'a> Searcher<'a> {
         depth: i16,
         f: impl FnOnce(&mut Self) -> Option<Eval>,
     ) -> Option<Eval> {
-        match position.board.status() {
-            cozy_chess::GameStatus::Drawn => return Some(Eval::DRAW),
-            cozy_chess::GameStatus::Won => return Some(-Eval::MATE.add_time(position.ply)),
-            cozy_chess::GameStatus::Ongoing => {}
+        // checkmate wins even on the move which reaches the fifty-move limit
+        let has_moves = position.board.generate_moves(|_| true);
+        if !has_moves && !position.board.checkers().is_empty() {
+            return Some(-Eval::MATE.add_time(position.ply));
+        }
+        if !has_moves || position.board.halfmove_clock() >= 100 {
+            return Some(self.draw_score(position));
         }
 
         if self.allow_abort && self.abort.load(Ordering::Relaxed) {
@@ -184,7 +361,7 @@ impl<'a> Searcher<'a> {
         hashmove: Option<Move>,
         mut window: Window,
         depth: i16,
-        mut f: impl FnMut(&mut Searcher, usize, Move, &Position, Window) -> Option<Eval>,
+        mut f: impl FnMut(&mut Searcher, usize, Move, MoveStage, i32, &Position, Window) -> Option<Eval>,
     ) -> Option<(Eval, Move)> {
         let mut best_move = INVALID_MOVE;
         let mut best_score = -Eval::MATE;
@@ -193,20 +370,29 @@ impl<'a> Searcher<'a> {
 
         let mut remaining = vec![];
 
-        self.visit_moves(position, hashmove, |this, mv| {
+        // only PV nodes get a full window, so only they need to keep track of the PV
+        let pv_node = window.ub() > window.lb() + 1;
+
+        self.visit_moves(position, hashmove, |this, mv, stage, score| {
+            if position.ply == 0 && !this.in_root_moves(mv) {
+                return Some(CONTINUE);
+            }
+
             let new_pos = position.play_move(mv);
+            if pv_node {
+                this.clear_pv(new_pos.ply);
+            }
 
             let v;
-            if let Some(eval) = oracle::oracle(&new_pos.board) {
-                v = eval;
-            } else if this.is_repetition(&new_pos.board) {
-                v = Eval::DRAW;
+            // the oracle only recognizes dead draws, so it gets the same score as a repetition
+            if oracle::oracle(&new_pos.board).is_some() || this.is_repetition(&new_pos.board) {
+                v = this.draw_score(position);
             } else {
                 if this.multithreaded
                     && i > 0
                     && this.shared.abdada.is_searching(new_pos.board.hash())
                 {
-                    remaining.push((i, mv, new_pos));
+                    remaining.push((i, mv, stage, score, new_pos));
                     i += 1;
                     return Some(CONTINUE);
                 }
@@ -216,14 +402,27 @@ impl<'a> Searcher<'a> {
                     true => this.shared.abdada.enter(new_pos.board.hash()),
                     false => None,
                 };
+                if position.ply == 0 {
+                    if let Some(report) = this.currmove {
+                        report(mv, i + 1);
+                    }
+                }
+                let nodes = this.stats.nodes.load(Ordering::Relaxed);
                 this.push_repetition(&new_pos.board);
-                v = f(this, i, mv, &new_pos, window)?;
+                v = f(this, i, mv, stage, score, &new_pos, window)?;
                 this.pop_repetition();
+                if position.ply == 0 {
+                    let nodes = this.stats.nodes.load(Ordering::Relaxed) - nodes;
+                    this.record_root_nodes(mv, nodes);
+                }
             }
 
             if v > best_score {
                 best_move = mv;
                 best_score = v;
+                if pv_node {
+                    this.update_pv(position.ply, mv);
+                }
             }
 
             if window.fail_high(v) {
@@ -238,16 +437,27 @@ impl<'a> Searcher<'a> {
             Some(CONTINUE)
         })?;
 
-        for (i, mv, new_pos) in remaining {
+        for (i, mv, stage, score, new_pos) in remaining {
             self.shared.tt.prefetch(&new_pos.board);
             self.push_repetition(&new_pos.board);
             let _guard = self.shared.abdada.enter(new_pos.board.hash());
-            let v = f(self, i, mv, &new_pos, window)?;
+            let nodes = self.stats.nodes.load(Ordering::Relaxed);
+            if pv_node {
+                self.clear_pv(new_pos.ply);
+            }
+            let v = f(self, i, mv, stage, score, &new_pos, window)?;
             self.pop_repetition();
+            if position.ply == 0 {
+                let nodes = self.stats.nodes.load(Ordering::Relaxed) - nodes;
+                self.record_root_nodes(mv, nodes);
+            }
 
             if v > best_score {
                 best_move = mv;
                 best_score = v;
+                if pv_node {
+                    self.update_pv(position.ply, mv);
+                }
             }
 
             if window.fail_high(v) {
@@ -259,7 +469,9 @@ impl<'a> Searcher<'a> {
             }
         }
 
-        if window.fail_high(best_score) {
+        if self.excluding_root_moves(position) {
+            // this isn't the true result for the root, so keep it out of the TT
+        } else if window.fail_high(best_score) {
             self.failed_high(position, depth, best_score, best_move);
         } else if raised_alpha {
             self.shared.tt.store(
@@ -303,6 +515,182 @@ impl<'a> Searcher<'a> {
         self.state.history.caused_cutoff(position, mv, depth);
     }
 
+    /// Probes the WDL tablebases, returning a TB win/loss score or a draw score on a hit.
+    #[cfg(feature = "syzygy")]
+    fn probe_tb(&self, position: &Position) -> Option<Eval> {
+        use cozy_syzygy::Wdl;
+
+        let tb = &self.shared.tb;
+        if position.board.occupied().len() > tb.max_pieces() {
+            return None;
+        }
+        Some(match tb.probe_wdl(&position.board)?.0 {
+            Wdl::Win => Eval::TB_WIN.add_time(position.ply),
+            Wdl::Loss => -Eval::TB_WIN.add_time(position.ply),
+            _ => self.draw_score(position),
+        })
+    }
+
+    #[cfg(not(feature = "syzygy"))]
+    fn probe_tb(&self, _: &Position) -> Option<Eval> {
+        None
+    }
+
+    /// If the tablebases cover every root move, restricts the root to the moves which keep the
+    /// best outcome, and among those to the ones which reach a capture or pawn move soonest when
+    /// winning or latest when losing, so that a tablebase win isn't drawn by the fifty-move rule.
+    /// Returns how many root moves are left if it filtered them.
+    #[cfg(feature = "syzygy")]
+    pub fn filter_tb_root_moves(&mut self) -> Option<usize> {
+        use cozy_syzygy::Wdl;
+
+        let tb = &self.shared.tb;
+        if self.root.occupied().len() > tb.max_pieces() {
+            return None;
+        }
+
+        let mut children = vec![];
+        self.root.generate_moves(|mvs| {
+            for mv in mvs {
+                if self.root_filter.is_empty() || self.root_filter.contains(&mv) {
+                    let mut child = self.root.clone();
+                    child.play_unchecked(mv);
+                    children.push((mv, child));
+                }
+            }
+            false
+        });
+
+        // rank each move by the outcome for us, then by how quickly it makes progress
+        let mut ranked = vec![];
+        for (mv, child) in children {
+            let rank = match child.generate_moves(|_| true) {
+                false if !child.checkers().is_empty() => (4, 0),
+                false => (2, 0),
+                true => {
+                    let outcome = match tb.probe_wdl(&child)?.0 {
+                        Wdl::Loss => 4,
+                        Wdl::BlessedLoss => 3,
+                        Wdl::Draw => 2,
+                        Wdl::CursedWin => 1,
+                        Wdl::Win => 0,
+                    };
+                    let dtz = match child.halfmove_clock() {
+                        0 => 0,
+                        _ => (tb.probe_dtz(&child)?.0).0.abs() as i32,
+                    };
+                    match outcome {
+                        2 => (2, 0),
+                        _ if outcome > 2 => (outcome, -dtz),
+                        _ => (outcome, dtz),
+                    }
+                }
+            };
+            ranked.push((mv, rank));
+        }
+
+        let &best = ranked.iter().map(|(_, rank)| rank).max()?;
+        self.root_filter = ranked
+            .into_iter()
+            .filter(|&(_, rank)| rank == best)
+            .map(|(mv, _)| mv)
+            .collect();
+        Some(self.root_filter.len())
+    }
+
+    #[cfg(not(feature = "syzygy"))]
+    pub fn filter_tb_root_moves(&mut self) -> Option<usize> {
+        None
+    }
+
+    /// The score of a draw for the side to move, biased against the engine by the contempt.
+    fn draw_score(&self, position: &Position) -> Eval {
+        // jitter draw scores slightly by position so that the search doesn't see every drawing
+        // line as equal and keep steering into the same repetitions
+        let jitter = draw_jitter(position.board.hash());
+
+        let contempt = self.contempt.saturating_mul(5);
+        match position.board.side_to_move() == self.root.side_to_move() {
+            true => Eval::DRAW + jitter - contempt,
+            false => Eval::DRAW + jitter + contempt,
+        }
+    }
+
+    /// Whether this is the root and some root moves are being skipped, either for MultiPV or
+    /// because of a `searchmoves` restriction.
+    fn excluding_root_moves(&self, position: &Position) -> bool {
+        position.ply == 0 && (!self.root_excluded.is_empty() || !self.root_filter.is_empty())
+    }
+
+    fn clear_pv(&mut self, ply: u16) {
+        let ply = ply as usize;
+        if self.pv_table.len() <= ply {
+            self.pv_table.resize_with(ply + 1, Vec::new);
+        }
+        self.pv_table[ply].clear();
+    }
+
+    /// Sets the PV at `ply` to `mv` followed by the PV of the child it leads to.
+    fn update_pv(&mut self, ply: u16, mv: Move) {
+        let ply = ply as usize;
+        if self.pv_table.len() < ply + 2 {
+            self.pv_table.resize_with(ply + 2, Vec::new);
+        }
+        let (parents, children) = self.pv_table.split_at_mut(ply + 1);
+        let pv = &mut parents[ply];
+        pv.clear();
+        pv.push(mv);
+        pv.extend_from_slice(&children[0]);
+    }
+
+    fn record_static_eval(&mut self, position: &Position) -> Eval {
+        let ply = position.ply as usize;
+        if self.static_evals.len() <= ply {
+            self.static_evals.resize(ply + 1, None);
+        }
+        let eval = position.static_eval();
+        self.static_evals[ply] = match position.board.checkers().is_empty() {
+            true => Some(eval),
+            false => None,
+        };
+        eval
+    }
+
+    /// Whether the static eval has gone up since two plies ago. Assumes it has if either side of
+    /// the comparison was in check. Requires `record_static_eval` to have been called for the node.
+    fn improving(&self, position: &Position) -> bool {
+        let ply = position.ply as usize;
+        let before = ply.checked_sub(2).and_then(|p| self.static_evals[p]);
+        match (self.static_evals[ply], before) {
+            (Some(now), Some(before)) => now > before,
+            _ => true,
+        }
+    }
+
+    fn record_root_nodes(&mut self, mv: Move, nodes: u64) {
+        match self.root_nodes.iter_mut().find(|(m, _)| *m == mv) {
+            Some(entry) => entry.1 = nodes,
+            None => self.root_nodes.push((mv, nodes)),
+        }
+        match self.root_node_totals.iter_mut().find(|(m, _)| *m == mv) {
+            Some(entry) => entry.1 += nodes,
+            None => self.root_node_totals.push((mv, nodes)),
+        }
+    }
+
+    fn in_root_moves(&self, mv: Move) -> bool {
+        !self.root_excluded.contains(&mv)
+            && (self.root_filter.is_empty() || self.root_filter.contains(&mv))
+    }
+
+    /// The move the seeded principal variation plays here, if this position is on it.
+    fn seeded_move(&self, position: &Position) -> Option<Move> {
+        match self.pv_seed.get(position.ply as usize) {
+            Some(&(hash, mv)) if hash == position.board.hash() => Some(mv),
+            _ => None,
+        }
+    }
+
     fn push_repetition(&mut self, board: &Board) {
         self.rep_table[board.hash() as usize % 1024] += 1;
         self.rep_list.push(board.hash());
@@ -323,18 +711,28 @@ impl<'a> Searcher<'a> {
             .rev()
             .take(board.halfmove_clock() as usize)
             .skip(1)
-            .any(|&b| b == board.hash())
+            .filter(|&&b| b == board.hash())
+            .nth(self.repetitions_needed - 1)
+            .is_some()
     }
 
-    pub fn extract_pv(&mut self, depth: i16) -> Vec<Move> {
+    /// The principal variation starting with the root move `first`. Lines recorded by the search
+    /// are preferred; otherwise it is reconstructed by following TT moves.
+    pub fn extract_pv(&mut self, first: Move, depth: i16) -> Vec<Move> {
+        if let Some(pv) = self.root_pvs.iter().find(|line| line[0] == first) {
+            return pv.clone();
+        }
+
         let mut board = self.root.clone();
         let mut pv = Vec::with_capacity(16);
-        while let Some(mv) = self.shared.tt.get_move(&board) {
+        let mut next = Some(first);
+        while let Some(mv) = next {
             pv.push(mv);
             board.play_unchecked(mv);
             if pv.len() > depth as usize {
                 break;
             }
+            next = self.shared.tt.get_move(&board);
         }
         pv
     }
@@ -344,3 +742,115 @@ fn estimate_nodes_to_deadline(d: Duration) -> u64 {
     // assume we get at least 1 mnps (very conservative)
     1000 * d.as_millis().min(1) as u64
 }
+
+#[cfg(test)]
+mod tests {
+    use super::params::{NMP_VERIFY_DEPTH, PROBCUT_MIN_DEPTH, PROBCUT_REDUCTION};
+    use super::*;
+
+    #[test]
+    fn improving_compares_with_two_plies_ago() {
+        let mut position = Position::from_root(Board::default());
+        for mv in ["e2e4", "e7e5"] {
+            position = position.play_move(mv.parse().unwrap());
+        }
+        let cases = [
+            ([Some(10), None, Some(20)], true),
+            ([Some(20), None, Some(10)], false),
+            ([Some(20), None, Some(20)], false),
+            // either side of the comparison being in check counts as improving
+            ([None, None, Some(10)], true),
+            ([Some(20), None, None], true),
+        ];
+
+        let mut engine = Frozenight::new(1);
+        let abort = AtomicBool::new(false);
+        engine.with_searcher(u64::MAX, false, &abort, None, |mut searcher| {
+            for (evals, improving) in cases {
+                searcher.static_evals = evals.iter().map(|e| e.map(Eval::new)).collect();
+                assert_eq!(searcher.improving(&position), improving, "{:?}", evals);
+            }
+
+            // without two plies of history there is nothing to compare against
+            let root = Position::from_root(Board::default());
+            searcher.static_evals = vec![Some(Eval::new(10))];
+            assert!(searcher.improving(&root));
+        });
+    }
+
+    #[test]
+    fn probcut_stores_a_reduced_lower_bound() {
+        // white is a queen down, but exd5 wins it back with room to spare above beta
+        let position = Position::from_root("4k3/8/8/3q4/4P3/8/8/4K3 w - - 0 1".parse().unwrap());
+        let window = Window::null(Eval::new(-2500));
+        let depth = PROBCUT_MIN_DEPTH.get();
+
+        let mut engine = Frozenight::new(1);
+        engine.set_pruning(Pruning::PROBCUT);
+        let abort = AtomicBool::new(false);
+        let (eval, entry) = engine.with_searcher(u64::MAX, false, &abort, None, |mut searcher| {
+            let eval = searcher.visit_null(&position, window, depth, true).unwrap();
+            (eval, searcher.shared.tt.get(&position).unwrap())
+        });
+        assert!(window.fail_high(eval), "{}", eval);
+
+        let capture: Move = "e4d5".parse().unwrap();
+        assert_eq!(entry.mv, capture);
+        assert_eq!(entry.kind, NodeKind::LowerBound);
+        assert_eq!(entry.depth, depth - PROBCUT_REDUCTION.get());
+    }
+
+    #[test]
+    fn null_move_verification_catches_zugzwang() {
+        // a trebuchet: whoever moves has to give up their pawn, and white's trapped bishop can't
+        // lose the tempo, so passing would be much better for white than any legal move
+        let white = "8/8/8/3Kp3/4Pk2/6p1/6P1/7B w - - 0 1";
+        let black = "8/8/8/3Kp3/4Pk2/6p1/6P1/7B b - - 0 1";
+        let depth = NMP_VERIFY_DEPTH.get() + 2;
+        let search = |fen: &str| {
+            let mut engine = Frozenight::new(1);
+            engine.set_position(fen.parse().unwrap(), std::iter::empty());
+            let time = crate::TimeConstraint {
+                depth,
+                ..crate::TimeConstraint::INFINITE
+            };
+            engine.search(time, |_| {}).eval
+        };
+        let actual = search(white);
+        let passed = -search(black);
+        assert!(actual < passed, "{} vs {}", actual, passed);
+
+        // a null window between the two must not fail high on the strength of the null move
+        let lb = (actual.raw() as i32 + passed.raw() as i32) / 2;
+        let window = Window::null(Eval::new(lb as i16));
+        let position = Position::from_root(white.parse().unwrap());
+        let mut engine = Frozenight::new(1);
+        let abort = AtomicBool::new(false);
+        let eval = engine.with_searcher(u64::MAX, false, &abort, None, |mut searcher| {
+            searcher
+                .visit_null(&position, window, depth, false)
+                .unwrap()
+        });
+        assert!(window.fail_low(eval), "{} with window {:?}", eval, window);
+    }
+
+    #[test]
+    fn extreme_contempt_saturates() {
+        let mut engine = Frozenight::new(1);
+        let root = Position::from_root(Board::default());
+        let reply = root.play_move("e2e4".parse().unwrap());
+        let abort = AtomicBool::new(false);
+        for contempt in [i16::MAX, i16::MIN] {
+            engine.set_contempt(contempt);
+            let (ours, theirs) = engine.with_searcher(u64::MAX, false, &abort, None, |searcher| {
+                (searcher.draw_score(&root), searcher.draw_score(&reply))
+            });
+            let expected = match contempt > 0 {
+                true => -Eval::MAX_INCONCLUSIVE,
+                false => Eval::MAX_INCONCLUSIVE,
+            };
+            assert_eq!(ours, expected);
+            assert_eq!(theirs, -expected);
+        }
+    }
+}
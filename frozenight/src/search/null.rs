@@ -1,21 +1,37 @@
 use crate::position::Position;
-use crate::tt::NodeKind;
+use crate::tt::{NodeKind, TableEntry};
 use crate::Eval;
 
+use super::ordering::{MoveStage, BREAK, CONTINUE};
 use super::params::*;
+use super::see::static_exchange_eval;
 use super::window::Window;
-use super::Searcher;
+use super::{Pruning, Searcher};
 
-use cozy_chess::Piece;
+use cozy_chess::{Move, Piece};
 
 impl Searcher<'_> {
-    pub fn visit_null(&mut self, position: &Position, window: Window, depth: i16) -> Option<Eval> {
+    /// Searches a non-PV node. `cut_node` is set where the node is expected to fail high, and
+    /// flips from one ply to the next.
+    pub fn visit_null(
+        &mut self,
+        position: &Position,
+        window: Window,
+        depth: i16,
+        cut_node: bool,
+    ) -> Option<Eval> {
         self.visit_node(position, window, depth, |this| {
-            this.null_search(position, window, depth)
+            this.null_search(position, window, depth, cut_node)
         })
     }
 
-    fn null_search(&mut self, position: &Position, window: Window, depth: i16) -> Option<Eval> {
+    fn null_search(
+        &mut self,
+        position: &Position,
+        window: Window,
+        depth: i16,
+        cut_node: bool,
+    ) -> Option<Eval> {
         let entry = self.shared.tt.get(position);
         if let Some(entry) = entry {
             match entry.kind {
@@ -34,69 +50,290 @@ impl Searcher<'_> {
             }
         };
 
+        if let Some(eval) = self.probe_tb(position) {
+            return Some(eval);
+        }
+
         // mate distance pruning
         let mate_score = Eval::MATE.add_time(position.ply);
         if window.fail_low(mate_score) {
             return Some(mate_score);
         }
+        let mated_score = -mate_score;
+        if window.fail_high(mated_score) {
+            return Some(mated_score);
+        }
+
+        // if our position hasn't improved since our last move, prune more aggressively
+        let static_eval = self.record_static_eval(position);
+        let not_improving = !self.improving(position) as i16;
+
+        let in_check = !position.board.checkers().is_empty();
 
         // reverse futility pruning... but with qsearch
-        if depth <= RFP_MAX_DEPTH.get() {
-            let rfp_window = Window::null(window.lb() + rfp_margin(depth));
+        if depth <= RFP_MAX_DEPTH.get() && self.pruning.contains(Pruning::RFP) && !in_check {
+            let rfp_window = Window::null(window.lb() + rfp_margin(depth - not_improving));
             let eval = entry
                 .map(|e| e.eval)
                 .unwrap_or_else(|| self.qsearch(position, rfp_window));
             if rfp_window.fail_high(eval) {
+                trace!("rfp", position, depth, window, "eval" => eval.raw());
                 return Some(eval);
             }
         }
 
+        // razoring: if even a large margin can't lift the static eval to alpha, trust qsearch
+        if depth <= RAZOR_MAX_DEPTH.get()
+            && self.pruning.contains(Pruning::RAZORING)
+            && !in_check
+            && !window.lb().is_conclusive()
+            && window.fail_low(static_eval + razor_margin(depth))
+        {
+            let v = self.qsearch(position, window);
+            if window.fail_low(v) {
+                trace!("razor", position, depth, window, "eval" => v.raw());
+                return Some(v);
+            }
+        }
+
         // null move pruning
         let our_sliders = (position.board.pieces(Piece::Rook)
             | position.board.pieces(Piece::Bishop)
             | position.board.pieces(Piece::Queen))
             & position.board.colors(position.board.side_to_move());
         let do_nmp = depth >= NMP_MIN_DEPTH.get()
+            && self.pruning.contains(Pruning::NULL_MOVE)
+            && position.ply >= self.nmp_min_ply
             && !our_sliders.is_empty()
-            && window.fail_high(position.static_eval());
+            && window.fail_high(static_eval);
         if do_nmp {
             if let Some(nm) = position.null_move() {
                 let reduction = nmp_reduction(depth);
-                let v = -self.visit_null(&nm, -window, depth - reduction - 1)?;
+                let v = -self.visit_null(&nm, -window, depth - reduction - 1, !cut_node)?;
                 if window.fail_high(v) {
-                    return Some(v);
+                    if depth < NMP_VERIFY_DEPTH.get() {
+                        trace!("nmp", position, depth, window, "eval" => v.raw());
+                        return Some(v);
+                    }
+
+                    // at high depth, verify with a reduced search of our own moves so that
+                    // zugzwang positions don't get pruned
+                    let prev_min_ply = self.nmp_min_ply;
+                    self.nmp_min_ply = position.ply + (depth - reduction) as u16 * 3 / 4;
+                    let verified =
+                        self.visit_null(position, window, depth - reduction - 1, cut_node);
+                    self.nmp_min_ply = prev_min_ply;
+                    if window.fail_high(verified?) {
+                        trace!("nmp", position, depth, window, "eval" => v.raw());
+                        return Some(v);
+                    }
+                }
+            }
+        }
+
+        // probcut: a good capture that beats beta by a margin in a reduced search would very
+        // likely beat beta in a full depth search too
+        if depth >= PROBCUT_MIN_DEPTH.get()
+            && self.pruning.contains(Pruning::PROBCUT)
+            && !in_check
+            && !window.lb().is_conclusive()
+        {
+            let probcut_window = Window::null(window.lb() + PROBCUT_MARGIN.get());
+            // a capture has to win at least the gap between the static eval and the raised beta
+            let see_threshold = (probcut_window.lb().raw() as i32 - static_eval.raw() as i32) / 5;
+            let probcut_depth = depth - PROBCUT_REDUCTION.get();
+            let mut cutoff = None;
+            self.visit_moves(position, None, |this, mv, stage, _| {
+                if stage != MoveStage::Capture {
+                    return Some(BREAK);
+                }
+                if static_exchange_eval(&position.board, mv) < see_threshold {
+                    return Some(CONTINUE);
+                }
+
+                let new_pos = position.play_move(mv);
+                let v = if this.is_repetition(&new_pos.board) {
+                    this.draw_score(position)
+                } else {
+                    this.push_repetition(&new_pos.board);
+                    let v =
+                        this.visit_null(&new_pos, -probcut_window, probcut_depth - 1, !cut_node);
+                    this.pop_repetition();
+                    -v?
+                };
+                if probcut_window.fail_high(v) {
+                    cutoff = Some((mv, v));
+                    return Some(BREAK);
+                }
+                Some(CONTINUE)
+            })?;
+            if let Some((mv, v)) = cutoff {
+                self.shared.tt.store(
+                    position,
+                    TableEntry {
+                        mv,
+                        eval: v,
+                        depth: probcut_depth,
+                        kind: NodeKind::LowerBound,
+                    },
+                );
+                trace!("probcut", position, depth, window, "eval" => v.raw());
+                return Some(v);
+            }
+        }
+
+        // multi-cut: at an expected cut node, if several of the first moves beat beta even in a
+        // reduced search, one of them would very likely beat beta in a full depth search too
+        if depth >= MC_MIN_DEPTH.get()
+            && self.pruning.contains(Pruning::MULTI_CUT)
+            && cut_node
+            && !in_check
+            && !window.lb().is_conclusive()
+        {
+            let mut tried = 0;
+            let mut cuts = 0;
+            let mut cutoff = None;
+            self.visit_moves(position, entry.map(|e| e.mv), |this, mv, _, _| {
+                let new_pos = position.play_move(mv);
+                let v = if this.is_repetition(&new_pos.board) {
+                    this.draw_score(position)
+                } else {
+                    this.push_repetition(&new_pos.board);
+                    let v = this.visit_null(
+                        &new_pos,
+                        -window,
+                        depth - MC_REDUCTION.get() - 1,
+                        !cut_node,
+                    );
+                    this.pop_repetition();
+                    -v?
+                };
+                tried += 1;
+                if window.fail_high(v) {
+                    cuts += 1;
+                    if cuts >= MC_CUTS.get() {
+                        cutoff = Some(v);
+                        return Some(BREAK);
+                    }
                 }
+                Some(tried >= MC_MOVES.get())
+            })?;
+            if let Some(v) = cutoff {
+                trace!("multicut", position, depth, window, "eval" => v.raw());
+                return Some(v);
             }
         }
 
+        // singular extensions: if every other move fails low against a margin below the TT
+        // score, the TT move is the only good move here and deserves a closer look
+        let singular_move = match entry {
+            Some(e)
+                if depth >= SE_MIN_DEPTH.get()
+                    && e.depth >= depth - 3
+                    && e.kind != NodeKind::UpperBound
+                    && !e.eval.is_conclusive() =>
+            {
+                let singular_beta = e.eval - SE_MARGIN.get().saturating_mul(depth);
+                if self.is_singular(position, e.mv, singular_beta, (depth - 1) / 2, cut_node)? {
+                    Some(e.mv)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        // futility pruning: near the leaves, quiet moves are unlikely to make up a large deficit
+        let futile = depth <= FP_MAX_DEPTH.get()
+            && self.pruning.contains(Pruning::FUTILITY)
+            && !in_check
+            && !window.lb().is_conclusive()
+            && window.fail_low(static_eval + futility_margin(depth - not_improving));
+
+        // a quiet TT move from an entry shallower than HASHMOVE_TRUST_DEPTH (off when 0) is only
+        // tried first if its history isn't poor; otherwise it competes with the other quiets
+        let hashmove = entry
+            .filter(|e| {
+                let piece = match position.board.piece_on(e.mv.from) {
+                    Some(piece) => piece,
+                    None => return true,
+                };
+                position.is_capture(e.mv)
+                    || e.mv.promotion.is_some()
+                    || e.depth >= HASHMOVE_TRUST_DEPTH.get()
+                    || self.state.history.rank(position, piece, e.mv) >= history_reduce_threshold()
+            })
+            .map(|e| e.mv);
+
+        // at an expected cut node, one of the first moves should cause the cutoff, so the later
+        // ones are less likely to matter
+        let cut_reduction = cut_node as i16;
+
         let mut yielded = Vec::with_capacity(64);
 
         self.search_moves(
             position,
-            entry.map(|e| e.mv),
+            hashmove,
             window,
             depth,
-            |this, i, mv, new_pos, window| {
+            |this, i, mv, stage, score, new_pos, window| {
+                if futile
+                    && i > 0
+                    && !position.is_capture(mv)
+                    && mv.promotion.is_none()
+                    && new_pos.board.checkers().is_empty()
+                {
+                    return Some(-Eval::MATE);
+                }
+
+                // history pruning: skip shallow quiets which have rarely caused cutoffs elsewhere.
+                // killers and countermoves come from a different stage and are never pruned.
+                let quiet = stage == MoveStage::Quiet && new_pos.board.checkers().is_empty();
+                if quiet
+                    && i > 0
+                    && depth <= HP_MAX_DEPTH.get()
+                    && this.pruning.contains(Pruning::HISTORY)
+                    && !in_check
+                    && !window.lb().is_conclusive()
+                    && score < history_prune_threshold()
+                {
+                    return Some(-Eval::MATE);
+                }
+
                 let extension = match () {
                     _ if !new_pos.board.checkers().is_empty() => 1,
+                    _ if Some(mv) == singular_move => 1,
                     _ => 0,
                 };
 
                 let reduction = match () {
                     _ if extension > 0 => -extension,
+                    _ if !this.pruning.contains(Pruning::LMR) => 0,
+                    _ if in_check => 0,
                     _ if position.is_capture(mv) => 0,
                     _ if !new_pos.board.checkers().is_empty() => 0,
-                    _ => null_lmr(depth, i),
+                    _ if stage.is_refutation() => 0,
+                    _ => {
+                        let mut reduction = null_lmr(depth, i);
+                        // the first move is the likeliest to be best, so it is never reduced more
+                        if i > 0 {
+                            reduction += not_improving + cut_reduction;
+                            if quiet && score < history_reduce_threshold() {
+                                reduction += 1;
+                            }
+                        }
+                        if quiet && score > history_good_threshold() {
+                            reduction -= 1;
+                        }
+                        reduction.clamp(0, depth - 1)
+                    }
                 };
+                trace!("move", position, depth, window, "move" => mv, "reduction" => reduction);
 
-                if window.lb() >= -Eval::MAX_INCONCLUSIVE && depth - reduction - 1 < 0 {
-                    return Some(-Eval::MATE);
-                }
-
-                let mut v = -this.visit_null(new_pos, -window, depth - reduction - 1)?;
+                let mut v = -this.visit_null(new_pos, -window, depth - reduction - 1, !cut_node)?;
 
                 if window.fail_high(v) && reduction > 0 {
-                    v = -this.visit_null(new_pos, -window, depth - 1)?;
+                    v = -this.visit_null(new_pos, -window, depth - 1, !cut_node)?;
                 }
 
                 if window.fail_high(v) {
@@ -113,4 +350,41 @@ impl Searcher<'_> {
         )
         .map(|(e, _)| e)
     }
+
+    /// Returns whether every move other than `tt_move` fails low against `beta`.
+    fn is_singular(
+        &mut self,
+        position: &Position,
+        tt_move: Move,
+        beta: Eval,
+        depth: i16,
+        cut_node: bool,
+    ) -> Option<bool> {
+        let window = Window::null(beta - 1);
+        let mut singular = true;
+
+        self.visit_moves(position, None, |this, mv, _, _| {
+            if mv == tt_move {
+                return Some(CONTINUE);
+            }
+
+            let new_pos = position.play_move(mv);
+            let v = if this.is_repetition(&new_pos.board) {
+                this.draw_score(position)
+            } else {
+                this.push_repetition(&new_pos.board);
+                let v = this.visit_null(&new_pos, -window, depth - 1, !cut_node);
+                this.pop_repetition();
+                -v?
+            };
+
+            if window.fail_high(v) {
+                singular = false;
+                return Some(BREAK);
+            }
+            Some(CONTINUE)
+        })?;
+
+        Some(singular)
+    }
 }
@@ -1,32 +1,110 @@
-use cozy_chess::{Color, Move, Piece, Square};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use cozy_chess::{get_knight_moves, Color, Move, Piece, Square};
 
 use crate::position::Position;
 
 use super::see::static_exchange_eval;
-use super::{Searcher, INVALID_MOVE};
+use super::Searcher;
 
 pub const CONTINUE: bool = false;
 pub const BREAK: bool = true;
 
+/// The stage a move was yielded from by `visit_moves`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MoveStage {
+    Hashmove,
+    Capture,
+    Killer,
+    Countermove,
+    Quiet,
+    LosingCapture,
+    Underpromotion,
+    /// A root move ranked by the size of its subtree in the previous iteration.
+    Root,
+}
+
+impl MoveStage {
+    /// Whether moves from this stage are expected to be good, so they shouldn't be reduced.
+    pub fn is_refutation(self) -> bool {
+        matches!(
+            self,
+            MoveStage::Hashmove | MoveStage::Killer | MoveStage::Countermove
+        )
+    }
+}
+
+/// The lists `visit_moves` generates moves into. Searchers keep a pool of these so that nodes
+/// don't allocate new ones.
+#[derive(Default)]
+pub struct MoveBuffers {
+    captures: Vec<(Move, i32)>,
+    capture_heap: BinaryHeap<(i32, Reverse<usize>)>,
+    quiets: Vec<(Move, Piece)>,
+    underpromotions: Vec<Move>,
+}
+
+impl MoveBuffers {
+    fn clear(&mut self) {
+        self.captures.clear();
+        self.capture_heap.clear();
+        self.quiets.clear();
+        self.underpromotions.clear();
+    }
+}
+
 impl Searcher<'_> {
+    /// Calls `search` with each legal move in order, along with the stage it was yielded from and
+    /// the score it was ranked by within that stage (zero for unranked stages). Stops early if
+    /// `search` returns `BREAK`.
     pub fn visit_moves(
         &mut self,
         position: &Position,
         hashmove: Option<Move>,
-        mut search: impl FnMut(&mut Searcher, Move) -> Option<bool>,
+        search: impl FnMut(&mut Searcher, Move, MoveStage, i32) -> Option<bool>,
+    ) -> Option<()> {
+        if position.ply == 0 && !self.root_nodes.is_empty() {
+            return self.visit_root_moves(position, hashmove, search);
+        }
+
+        // nested calls each take their own buffers from the pool
+        let mut buffers = self.move_buffers.pop().unwrap_or_default();
+        buffers.clear();
+        let result = self.visit_ordered_moves(position, hashmove, &mut buffers, search);
+        self.move_buffers.push(buffers);
+        result
+    }
+
+    fn visit_ordered_moves(
+        &mut self,
+        position: &Position,
+        hashmove: Option<Move>,
+        buffers: &mut MoveBuffers,
+        mut search: impl FnMut(&mut Searcher, Move, MoveStage, i32) -> Option<bool>,
     ) -> Option<()> {
-        // Hashmove
+        // Hashmove. TT moves are checked when probed, but callers may pass anything, so make sure
+        // we only ever yield legal moves.
+        let hashmove = hashmove.filter(|&mv| position.board.is_legal(mv));
         if let Some(mv) = hashmove {
-            if search(self, mv)? {
+            if search(self, mv, MoveStage::Hashmove, 0)? {
                 return Some(());
             }
         }
 
         // Generate moves.
-        let mut captures = Vec::with_capacity(16);
-        let mut quiets = Vec::with_capacity(64);
-        let mut underpromotions = vec![];
-        let killer = self.state.history.killer(position.ply);
+        let MoveBuffers {
+            captures,
+            capture_heap,
+            quiets,
+            underpromotions,
+        } = buffers;
+        let killers = self.state.history.killers(position.ply);
+        let mut legal_killers = [None; 2];
+        let countermove = self.state.history.countermove(position);
+        let mut legal_countermove = None;
+        let their_king = position.board.king(!position.board.side_to_move());
+        let in_check = !position.board.checkers().is_empty();
 
         position.board.generate_moves(|mvs| {
             for mv in mvs {
@@ -37,17 +115,34 @@ impl Searcher<'_> {
                     mv.promotion,
                     Some(Piece::Knight | Piece::Bishop | Piece::Rook)
                 ) {
-                    underpromotions.push(mv);
+                    // knight underpromotions which give check are worth trying among the quiets
+                    let checks = mv.promotion == Some(Piece::Knight)
+                        && get_knight_moves(mv.to).has(their_king);
+                    match checks {
+                        true => quiets.push((mv, mvs.piece)),
+                        false => underpromotions.push(mv),
+                    }
                     continue;
                 }
 
-                if position.is_capture(mv) {
-                    let victim = position.board.piece_on(mv.to).unwrap();
+                if let Some(victim) = position.capture_victim(mv) {
                     let mvv_lva = 8 * victim as i32 - mvs.piece as i32 + 8;
-                    captures.push((mv, static_exchange_eval(&position.board, mv) + mvv_lva));
-                } else if mv == killer {
-                    // Killer is legal; order it after neutral captures
-                    captures.push((mv, 0));
+                    let see = static_exchange_eval(&position.board, mv);
+                    // capture history only breaks ties, so the sign still tells losing captures
+                    let history = self
+                        .state
+                        .history
+                        .capture_rank(position, mvs.piece, mv, victim);
+                    captures.push((mv, (see + mvv_lva) * 1024 + history));
+                } else if in_check {
+                    // evasions are all ordered together with the quiets below
+                    quiets.push((mv, mvs.piece));
+                } else if Some(mv) == killers[0] {
+                    legal_killers[0] = Some(mv);
+                } else if Some(mv) == killers[1] {
+                    legal_killers[1] = Some(mv);
+                } else if Some(mv) == countermove {
+                    legal_countermove = Some(mv);
                 } else {
                     quiets.push((mv, mvs.piece));
                 }
@@ -55,64 +150,116 @@ impl Searcher<'_> {
             false
         });
 
+        // Heapify the captures so each one taken only costs a pop. Ties go to the move generated
+        // first.
+        capture_heap.extend(
+            captures
+                .iter()
+                .enumerate()
+                .map(|(i, &(_, score))| (score, Reverse(i))),
+        );
+
         // Iterate winning & netrual captures
-        while !captures.is_empty() {
-            let mut index = 0;
-            for i in 1..captures.len() {
-                if captures[i].1 > captures[index].1 {
-                    index = i;
-                }
+        while let Some(&(score, Reverse(i))) = capture_heap.peek() {
+            if score < 0 {
+                break;
+            }
+            capture_heap.pop();
+            if search(self, captures[i].0, MoveStage::Capture, score)? {
+                return Some(());
             }
+        }
 
-            if captures[index].1 < 0 {
-                break;
+        // Killers, then countermove, ahead of the other quiets when not in check
+        for mv in legal_killers.into_iter().flatten() {
+            if search(self, mv, MoveStage::Killer, 0)? {
+                return Some(());
             }
-            if search(self, captures.swap_remove(index).0)? {
+        }
+        if let Some(mv) = legal_countermove {
+            if search(self, mv, MoveStage::Countermove, 0)? {
                 return Some(());
             }
         }
 
-        // Iterate quiets
+        // Iterate quiets. When evading check, blocks come before king moves, and the killers and
+        // countermove only come first among the blocks or king moves they belong to.
+        let refutation = |mv: Move| match () {
+            _ if !in_check => (0, MoveStage::Quiet),
+            _ if Some(mv) == killers[0] => (3, MoveStage::Killer),
+            _ if Some(mv) == killers[1] => (2, MoveStage::Killer),
+            _ if Some(mv) == countermove => (1, MoveStage::Countermove),
+            _ => (0, MoveStage::Quiet),
+        };
+        let key = |this: &Self, (mv, piece): (Move, Piece)| {
+            let block = !(in_check && piece == Piece::King);
+            let rank = this.state.history.rank(position, piece, mv);
+            (block, refutation(mv).0, rank)
+        };
         while !quiets.is_empty() {
             let mut index = 0;
-            let mut rank =
-                self.state
-                    .history
-                    .rank(quiets[0].1, quiets[0].0, position.board.side_to_move());
+            let mut best = key(self, quiets[0]);
             for i in 1..quiets.len() {
-                let r = self.state.history.rank(
-                    quiets[i].1,
-                    quiets[i].0,
-                    position.board.side_to_move(),
-                );
-                if r > rank {
+                let k = key(self, quiets[i]);
+                if k > best {
                     index = i;
-                    rank = r;
+                    best = k;
                 }
             }
 
-            if search(self, quiets.swap_remove(index).0)? {
+            let mv = quiets.swap_remove(index).0;
+            if search(self, mv, refutation(mv).1, best.2)? {
                 return Some(());
             }
         }
 
         // Iterate losing captures
-        while !captures.is_empty() {
-            let mut index = 0;
-            for i in 1..captures.len() {
-                if captures[i].1 > captures[index].1 {
-                    index = i;
-                }
-            }
-
-            if search(self, captures.swap_remove(index).0)? {
+        while let Some((score, Reverse(i))) = capture_heap.pop() {
+            if search(self, captures[i].0, MoveStage::LosingCapture, score)? {
                 return Some(());
             }
         }
 
         // Iterate underpromotions
         while let Some(mv) = underpromotions.pop() {
-            if search(self, mv)? {
+            if search(self, mv, MoveStage::Underpromotion, 0)? {
+                return Some(());
+            }
+        }
+
+        Some(())
+    }
+
+    /// Root move ordering once an iteration has completed. After the hashmove, moves which took
+    /// more nodes to search last time come first, since they were harder to refute.
+    fn visit_root_moves(
+        &mut self,
+        position: &Position,
+        hashmove: Option<Move>,
+        mut search: impl FnMut(&mut Searcher, Move, MoveStage, i32) -> Option<bool>,
+    ) -> Option<()> {
+        let hashmove = hashmove.filter(|&mv| position.board.is_legal(mv));
+        if let Some(mv) = hashmove {
+            if search(self, mv, MoveStage::Hashmove, 0)? {
+                return Some(());
+            }
+        }
+
+        let mut moves = self.root_nodes.clone();
+        moves.retain(|&(mv, _)| Some(mv) != hashmove);
+        moves.sort_by_key(|&(_, nodes)| Reverse(nodes));
+        position.board.generate_moves(|mvs| {
+            for mv in mvs {
+                if Some(mv) != hashmove && moves.iter().all(|&(m, _)| m != mv) {
+                    moves.push((mv, 0));
+                }
+            }
+            false
+        });
+
+        for (mv, nodes) in moves {
+            let score = nodes.min(i32::MAX as u64) as i32;
+            if search(self, mv, MoveStage::Root, score)? {
                 return Some(());
             }
         }
@@ -124,7 +271,11 @@ impl Searcher<'_> {
 pub struct OrderingState {
     piece_to_sq: ColorTable<PieceTable<SquareTable<HistoryCounter>>>,
     from_sq_to_sq: ColorTable<SquareTable<SquareTable<HistoryCounter>>>,
-    killers: [Move; 256],
+    killers: [[Option<Move>; 2]; 256],
+    countermoves: ColorTable<PieceTable<SquareTable<Option<Move>>>>,
+    capture_history: ColorTable<PieceTable<SquareTable<PieceTable<HistoryCounter>>>>,
+    /// Quiet history following the moves 1 and 2 plies ago, see `continuation_index`.
+    continuation: [Box<[PieceTable<SquareTable<HistoryCounter>>]>; 2],
 }
 
 impl OrderingState {
@@ -132,7 +283,14 @@ impl OrderingState {
         OrderingState {
             piece_to_sq: Default::default(),
             from_sq_to_sq: Default::default(),
-            killers: [INVALID_MOVE; 256],
+            killers: [[None; 2]; 256],
+            countermoves: Default::default(),
+            capture_history: Default::default(),
+            continuation: [(); 2].map(|_| {
+                (0..Color::NUM * Piece::NUM * Square::NUM)
+                    .map(|_| Default::default())
+                    .collect()
+            }),
         }
     }
 
@@ -143,19 +301,50 @@ impl OrderingState {
         for counter in (&mut self.from_sq_to_sq).into_iter().flatten().flatten() {
             counter.decay(16);
         }
+        for counter in (&mut self.capture_history)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .flatten()
+        {
+            counter.decay(64);
+        }
+        for counter in self
+            .continuation
+            .iter_mut()
+            .flat_map(|table| table.iter_mut())
+            .flatten()
+            .flatten()
+        {
+            counter.decay(64);
+        }
     }
 
     pub fn caused_cutoff(&mut self, pos: &Position, mv: Move, depth: i16) {
         let stm = pos.board.side_to_move();
         let piece = pos.board.piece_on(mv.from).unwrap();
-        let capture = pos.is_capture(mv);
 
-        if !capture {
+        if let Some(victim) = pos.capture_victim(mv) {
+            self.capture_history[stm][piece][mv.to][victim].increment(depth);
+        } else {
             self.piece_to_sq[stm][piece][mv.to].increment(depth);
             self.from_sq_to_sq[stm][mv.from][mv.to].increment(depth);
 
-            if let Some(killer) = self.killers.get_mut(pos.ply as usize) {
-                *killer = mv;
+            if let Some(killers) = self.killers.get_mut(pos.ply as usize) {
+                if killers[0] != Some(mv) {
+                    killers[1] = killers[0];
+                    killers[0] = Some(mv);
+                }
+            }
+
+            if let Some((prev_piece, prev_to)) = pos.last_move {
+                self.countermoves[stm][prev_piece][prev_to] = Some(mv);
+            }
+
+            for (table, prev) in self.continuation.iter_mut().zip(previous_moves(pos)) {
+                if let Some(idx) = continuation_index(stm, prev) {
+                    table[idx][piece][mv.to].increment(depth);
+                }
             }
         }
     }
@@ -163,26 +352,57 @@ impl OrderingState {
     pub fn did_not_cause_cutoff(&mut self, pos: &Position, mv: Move) {
         let stm = pos.board.side_to_move();
         let piece = pos.board.piece_on(mv.from).unwrap();
-        let capture = pos.is_capture(mv);
 
-        if !capture {
+        if let Some(victim) = pos.capture_victim(mv) {
+            self.capture_history[stm][piece][mv.to][victim].decrement();
+        } else {
             self.piece_to_sq[stm][piece][mv.to].decrement();
             self.from_sq_to_sq[stm][mv.from][mv.to].decrement();
+
+            for (table, prev) in self.continuation.iter_mut().zip(previous_moves(pos)) {
+                if let Some(idx) = continuation_index(stm, prev) {
+                    table[idx][piece][mv.to].decrement();
+                }
+            }
         }
     }
 
-    fn rank(&self, piece: Piece, mv: Move, stm: Color) -> i32 {
+    pub fn rank(&self, pos: &Position, piece: Piece, mv: Move) -> i32 {
+        let stm = pos.board.side_to_move();
         let piece_to = self.piece_to_sq[stm][piece][mv.to].value;
         let from_to = self.from_sq_to_sq[stm][mv.from][mv.to].value;
-        piece_to + from_to
+        let mut rank = piece_to + from_to;
+        for (table, prev) in self.continuation.iter().zip(previous_moves(pos)) {
+            rank += match continuation_index(stm, prev) {
+                Some(idx) => table[idx][piece][mv.to].value,
+                None => HistoryCounter::default().value,
+            };
+        }
+        rank
+    }
+
+    fn capture_rank(&self, pos: &Position, piece: Piece, mv: Move, victim: Piece) -> i32 {
+        let stm = pos.board.side_to_move();
+        (self.capture_history[stm][piece][mv.to][victim].value / 20_000).min(1023)
     }
 
-    fn killer(&self, ply: u16) -> Move {
-        self.killers
-            .get(ply as usize)
-            .copied()
-            .unwrap_or(INVALID_MOVE)
+    fn killers(&self, ply: u16) -> [Option<Move>; 2] {
+        self.killers.get(ply as usize).copied().unwrap_or([None; 2])
     }
+
+    fn countermove(&self, pos: &Position) -> Option<Move> {
+        let (prev_piece, prev_to) = pos.last_move?;
+        self.countermoves[pos.board.side_to_move()][prev_piece][prev_to]
+    }
+}
+
+fn previous_moves(pos: &Position) -> [Option<(Piece, Square)>; 2] {
+    [pos.last_move, pos.second_last_move]
+}
+
+fn continuation_index(stm: Color, prev: Option<(Piece, Square)>) -> Option<usize> {
+    let (piece, sq) = prev?;
+    Some((stm as usize * Piece::NUM + piece as usize) * Square::NUM + sq as usize)
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -266,3 +486,263 @@ tables! {
     PieceTable: Piece;
     SquareTable: Square;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use cozy_chess::{Board, Move};
+
+    use super::{continuation_index, MoveStage, CONTINUE};
+    use crate::position::Position;
+    use crate::Frozenight;
+
+    fn mv(mv: &str) -> Option<Move> {
+        Some(mv.parse().unwrap())
+    }
+
+    fn position(fen: &str, moves: &[&str]) -> Position {
+        let board: Board = fen.parse().unwrap();
+        let mut position = Position::from_root(board);
+        for &m in moves {
+            position = position.play_move(m.parse().unwrap());
+        }
+        position
+    }
+
+    /// The moves `visit_moves` yields, in order, with their stages and scores.
+    fn visit_order(
+        engine: &mut Frozenight,
+        position: &Position,
+        hashmove: Option<Move>,
+    ) -> Vec<(Move, MoveStage, i32)> {
+        let abort = AtomicBool::new(false);
+        let mut visited = vec![];
+        engine.with_searcher(u64::MAX, false, &abort, None, |mut searcher| {
+            searcher.visit_moves(position, hashmove, |_, mv, stage, score| {
+                visited.push((mv, stage, score));
+                Some(CONTINUE)
+            })
+        });
+        visited
+    }
+
+    /// Checks that `visit_moves` yields every legal move exactly once, whatever the ordering
+    /// heuristics are fed.
+    fn assert_visits_legal_moves(
+        position: &Position,
+        hashmove: Option<Move>,
+        killers: [Option<Move>; 2],
+        countermove: Option<Move>,
+    ) {
+        let mut engine = Frozenight::new(1);
+        engine.state.history.killers[position.ply as usize] = killers;
+        if let Some((piece, to)) = position.last_move {
+            engine.state.history.countermoves[position.board.side_to_move()][piece][to] =
+                countermove;
+        }
+
+        let mut visited: Vec<_> = visit_order(&mut engine, position, hashmove)
+            .into_iter()
+            .map(|(mv, _, _)| mv.to_string())
+            .collect();
+
+        let mut legal = vec![];
+        position.board.generate_moves(|mvs| {
+            legal.extend(mvs.into_iter().map(|mv| mv.to_string()));
+            false
+        });
+
+        visited.sort();
+        legal.sort();
+        assert_eq!(visited, legal);
+    }
+
+    const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    #[test]
+    fn illegal_hashmove() {
+        let startpos = position(STARTPOS, &[]);
+        assert_visits_legal_moves(&startpos, mv("e2e5"), [None; 2], None);
+        assert_visits_legal_moves(&startpos, mv("e7e5"), [None; 2], None);
+    }
+
+    #[test]
+    fn killer_is_hashmove() {
+        let startpos = position(STARTPOS, &[]);
+        assert_visits_legal_moves(&startpos, mv("e2e4"), [mv("e2e4"), mv("d2d4")], None);
+    }
+
+    #[test]
+    fn duplicate_killers() {
+        let startpos = position(STARTPOS, &[]);
+        assert_visits_legal_moves(&startpos, None, [mv("g1f3"), mv("g1f3")], None);
+    }
+
+    #[test]
+    fn countermove_is_killer() {
+        let position = position(STARTPOS, &["e2e4"]);
+        let killers = [mv("g8f6"), mv("b8c6")];
+        assert_visits_legal_moves(&position, None, killers, mv("g8f6"));
+        assert_visits_legal_moves(&position, None, killers, mv("b8c6"));
+    }
+
+    #[test]
+    fn in_check() {
+        let position = position("r3k3/8/8/8/8/5B2/8/4K2R b K - 0 1", &["a8a1"]);
+        let killers = [mv("f3d1"), mv("h1h8")];
+        assert_visits_legal_moves(&position, mv("e1g1"), killers, mv("e1e2"));
+        assert_visits_legal_moves(&position, mv("e1d2"), killers, mv("f3d1"));
+    }
+
+    #[test]
+    fn a1_killers() {
+        // moves from A1 are real killers, not empty slots
+        let position = position("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", &[]);
+        let killers = [mv("a1a5"), mv("e1d1")];
+        assert_visits_legal_moves(&position, None, killers, None);
+
+        let mut engine = Frozenight::new(1);
+        engine.state.history.killers[0] = killers;
+        let order = visit_order(&mut engine, &position, None);
+        assert_eq!(order[0].0, killers[0].unwrap());
+        assert_eq!(order[1].0, killers[1].unwrap());
+        assert!(order[..2]
+            .iter()
+            .all(|&(_, stage, _)| stage == MoveStage::Killer));
+        assert!(order[2..]
+            .iter()
+            .all(|&(_, stage, _)| stage == MoveStage::Quiet));
+    }
+
+    #[test]
+    fn capture_history_breaks_ties() {
+        // both rooks win an undefended pawn, so only capture history tells them apart
+        let position = position("4k3/8/8/p6p/8/8/8/R3K2R w - - 0 1", &[]);
+        for good in ["a1a5", "h1h5"] {
+            let mut engine = Frozenight::new(1);
+            engine
+                .state
+                .history
+                .caused_cutoff(&position, good.parse().unwrap(), 10);
+            let order = visit_order(&mut engine, &position, None);
+            let captures: Vec<_> = order
+                .iter()
+                .filter(|&&(_, stage, _)| stage == MoveStage::Capture)
+                .map(|&(mv, _, score)| (mv.to_string(), score))
+                .collect();
+            assert_eq!(captures.len(), 2);
+            assert_eq!(captures[0].0, good);
+            assert!(captures[0].1 > captures[1].1, "{:?}", captures);
+        }
+    }
+
+    #[test]
+    fn continuation_history_ranks_quiets() {
+        // 1 ply back, the opponent's move; 2 plies back, our own previous move
+        for (plies_back, moves) in [(0, &["e2e4"][..]), (1, &["e2e4", "e7e5"][..])] {
+            let position = position(STARTPOS, moves);
+            let quiets = |engine: &mut Frozenight| -> Vec<_> {
+                visit_order(engine, &position, None)
+                    .into_iter()
+                    .filter(|&(_, stage, _)| stage == MoveStage::Quiet)
+                    .collect()
+            };
+
+            // with neutral history, pick the quiet which comes last
+            let (target, _, neutral) = *quiets(&mut Frozenight::new(1)).last().unwrap();
+
+            let mut engine = Frozenight::new(1);
+            let stm = position.board.side_to_move();
+            let prev = [position.last_move, position.second_last_move][plies_back];
+            let idx = continuation_index(stm, prev).unwrap();
+            let piece = position.board.piece_on(target.from).unwrap();
+            engine.state.history.continuation[plies_back][idx][piece][target.to].increment(10);
+
+            let (first, _, score) = quiets(&mut engine)[0];
+            assert_eq!(first, target, "{:?}", moves);
+            assert!(score > neutral);
+        }
+    }
+
+    #[test]
+    fn captures_come_in_score_order() {
+        // the selection scan the capture heap replaced; its order among equal scores depended on
+        // earlier swap_removes, while the heap keeps generation order for ties
+        fn selection_order(mut captures: Vec<(Move, i32)>) -> Vec<(Move, i32)> {
+            let mut order = vec![];
+            while !captures.is_empty() {
+                let mut index = 0;
+                for i in 1..captures.len() {
+                    if captures[i].1 > captures[index].1 {
+                        index = i;
+                    }
+                }
+                order.push(captures.swap_remove(index));
+            }
+            order
+        }
+
+        let position = position(KIWIPETE, &[]);
+        let captures: Vec<_> = visit_order(&mut Frozenight::new(1), &position, None)
+            .into_iter()
+            .filter(|&(_, stage, _)| matches!(stage, MoveStage::Capture | MoveStage::LosingCapture))
+            .map(|(mv, _, score)| (mv, score))
+            .collect();
+        assert!(captures.len() > 5);
+
+        let scores = |order: &[(Move, i32)]| order.iter().map(|&(_, s)| s).collect::<Vec<_>>();
+        assert_eq!(
+            scores(&captures),
+            scores(&selection_order(captures.clone()))
+        );
+
+        // and ties go to the move generated first
+        let mut generated = vec![];
+        position.board.generate_moves(|mvs| {
+            generated.extend(mvs.into_iter().filter(|&mv| position.is_capture(mv)));
+            false
+        });
+        let index = |mv: Move| generated.iter().position(|&m| m == mv).unwrap();
+        for pair in captures.windows(2) {
+            if pair[0].1 == pair[1].1 {
+                assert!(index(pair[0].0) < index(pair[1].0));
+            }
+        }
+    }
+
+    #[test]
+    fn reused_buffers_order_like_fresh_ones() {
+        let kiwipete = position(KIWIPETE, &[]);
+        let nxf7: Move = "e5f7".parse().unwrap();
+        let child = kiwipete.play_move(nxf7);
+        let startpos = position(STARTPOS, &[]);
+        let fresh = |position: &Position| visit_order(&mut Frozenight::new(1), position, None);
+
+        // visit the child while the parent's buffers are taken, then visit another
+        // position with the buffers both nodes left behind
+        let mut engine = Frozenight::new(1);
+        let abort = AtomicBool::new(false);
+        let mut nested = vec![];
+        let mut after = vec![];
+        engine.with_searcher(u64::MAX, false, &abort, None, |mut searcher| {
+            searcher.visit_moves(&kiwipete, None, |searcher, mv, _, _| {
+                if mv == nxf7 {
+                    searcher.visit_moves(&child, None, |_, mv, stage, score| {
+                        nested.push((mv, stage, score));
+                        Some(CONTINUE)
+                    })?;
+                }
+                Some(CONTINUE)
+            });
+            searcher.visit_moves(&startpos, None, |_, mv, stage, score| {
+                after.push((mv, stage, score));
+                Some(CONTINUE)
+            });
+        });
+
+        assert_eq!(nested, fresh(&child));
+        assert_eq!(after, fresh(&startpos));
+    }
+}
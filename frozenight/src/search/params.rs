@@ -88,9 +88,27 @@ tweakables! {
     RFP_MARGIN_C: 0..=5000 = 11;
     RFP_MAX_DEPTH: 1..=20 = 8;
 
+    RAZOR_MARGIN_M: 0..=5000 = 1000;
+    RAZOR_MARGIN_C: 0..=5000 = 500;
+    RAZOR_MAX_DEPTH: 1..=20 = 2;
+
+    FP_MARGIN_M: 0..=5000 = 750;
+    FP_MARGIN_C: 0..=5000 = 0;
+    FP_MAX_DEPTH: 1..=20 = 6;
+
     NMP_MIN_DEPTH: 1..=20 = 1;
     NMP_REDUCTION_M: 0..=128 = 77;
     NMP_REDUCTION_C: 0..=1024 = 38;
+    NMP_VERIFY_DEPTH: 1..=100 = 12;
+
+    PROBCUT_MIN_DEPTH: 1..=100 = 5;
+    PROBCUT_MARGIN: 0..=5000 = 500;
+    PROBCUT_REDUCTION: 1..=20 = 4;
+
+    MC_MIN_DEPTH: 1..=100 = 8;
+    MC_MOVES: 1..=64 = 6;
+    MC_CUTS: 1..=64 = 3;
+    MC_REDUCTION: 1..=20 = 4;
 
     LMR_I1_M: 0..=256 = 92;
     LMR_I1_C: 0..=1024 = 15;
@@ -99,11 +117,67 @@ tweakables! {
     LMR_D_M: 0..=256 = 28;
     LMR_D_C: 0..=1024 = 8;
     PV_LMR_FACTOR: 0..=128 = 74;
+
+    IIR_MIN_DEPTH: 1..=20 = 4;
+
+    HASHMOVE_TRUST_DEPTH: 0..=100 = 0;
+
+    DELTA_MARGIN: 0..=5000 = 1000;
+    QS_SEE_THRESHOLD: -1000..=1000 = 0;
+
+    HP_MAX_DEPTH: 1..=20 = 3;
+    HP_THRESHOLD: 0..=1000 = 25;
+    HR_THRESHOLD: 0..=1000 = 75;
+    HG_THRESHOLD: 0..=1000 = 150;
+
+    SE_MIN_DEPTH: 1..=100 = 8;
+    SE_MARGIN: 0..=500 = 10;
+
+    // 0 scores every draw exactly, as if there were no jitter
+    DRAW_JITTER: 0..=100 = 5;
+
+    ASPIRATION_DELTA: 1..=2000 = 125;
+    ASPIRATION_RESEARCHES: 0..=16 = 4;
 }
 
 #[inline(always)]
 pub fn rfp_margin(depth: i16) -> i16 {
-    RFP_MARGIN_M.get() * depth + RFP_MARGIN_C.get()
+    RFP_MARGIN_M
+        .get()
+        .saturating_mul(depth)
+        .saturating_add(RFP_MARGIN_C.get())
+}
+
+#[inline(always)]
+pub fn razor_margin(depth: i16) -> i16 {
+    RAZOR_MARGIN_M
+        .get()
+        .saturating_mul(depth)
+        .saturating_add(RAZOR_MARGIN_C.get())
+}
+
+#[inline(always)]
+pub fn futility_margin(depth: i16) -> i16 {
+    FP_MARGIN_M
+        .get()
+        .saturating_mul(depth)
+        .saturating_add(FP_MARGIN_C.get())
+}
+
+/// History thresholds are a percentage of the rank of a quiet move without any history.
+#[inline(always)]
+pub fn history_prune_threshold() -> i32 {
+    HP_THRESHOLD.get() as i32 * 40_000
+}
+
+#[inline(always)]
+pub fn history_reduce_threshold() -> i32 {
+    HR_THRESHOLD.get() as i32 * 40_000
+}
+
+#[inline(always)]
+pub fn history_good_threshold() -> i32 {
+    HG_THRESHOLD.get() as i32 * 40_000
 }
 
 #[inline(always)]
@@ -137,3 +211,12 @@ fn linear(x: i16, m: i16, c: i16) -> i32 {
 fn trunc(v: i32) -> i16 {
     (v / 128) as i16
 }
+
+/// A small position-dependent offset in `-DRAW_JITTER..=DRAW_JITTER` for draw scores.
+#[inline(always)]
+pub fn draw_jitter(hash: u64) -> i16 {
+    match DRAW_JITTER.get() {
+        0 => 0,
+        jitter => (hash % (2 * jitter as u64 + 1)) as i16 - jitter,
+    }
+}
@@ -4,9 +4,10 @@ use crate::position::Position;
 use crate::tt::NodeKind;
 use crate::Eval;
 
+use super::ordering::MoveStage;
 use super::params::*;
 use super::window::Window;
-use super::Searcher;
+use super::{Pruning, Searcher, INVALID_MOVE};
 
 impl Searcher<'_> {
     pub fn pv_search(
@@ -15,10 +16,31 @@ impl Searcher<'_> {
         window: Window,
         depth: i16,
     ) -> Option<(Eval, Move)> {
+        trace!("pv", position, depth, window, "eval" => position.static_eval().raw());
+        self.clear_pv(position.ply);
+        self.record_static_eval(position);
+
+        // mate distance pruning: nothing here can beat mating next move or be worse than being
+        // mated now, so narrow the window to that range
+        let window = match position.ply {
+            0 => window,
+            ply => {
+                let mate_score = Eval::MATE.add_time(ply);
+                if window.fail_low(mate_score) {
+                    return Some((mate_score, INVALID_MOVE));
+                }
+                if window.fail_high(-mate_score) {
+                    return Some((-mate_score, INVALID_MOVE));
+                }
+                Window::new(window.lb().max(-mate_score), window.ub().min(mate_score))
+            }
+        };
+
+        let seeded = self.seeded_move(position);
         let hashmove = match self.shared.tt.get(position) {
             None => None,
             Some(entry) => {
-                if entry.depth >= depth {
+                if entry.depth >= depth && !self.excluding_root_moves(position) {
                     match entry.kind {
                         NodeKind::Exact => {
                             if depth < 2 {
@@ -38,7 +60,7 @@ impl Searcher<'_> {
                     }
                 }
                 let tt_not_good_enough = entry.depth < depth - 2 || entry.kind != NodeKind::Exact;
-                if tt_not_good_enough && depth > 3 {
+                if tt_not_good_enough && depth > 3 && seeded.is_none() {
                     // internal iterative deepening
                     Some(self.pv_search(position, window, depth - 2)?.1)
                 } else {
@@ -47,12 +69,21 @@ impl Searcher<'_> {
             }
         };
 
+        let hashmove = seeded.or(hashmove);
+
+        // internal iterative reductions: without a hashmove our ordering is poor, so search this
+        // node shallower and let the next iteration benefit from the move it stores
+        let depth = match hashmove {
+            None if position.ply > 0 && depth >= IIR_MIN_DEPTH.get() => depth - 1,
+            _ => depth,
+        };
+
         self.search_moves(
             position,
             hashmove,
             window,
             depth,
-            |this, i, mv, new_pos, window| {
+            |this, i, mv, stage, score, new_pos, window| {
                 let extension = match () {
                     _ if !new_pos.board.checkers().is_empty() => 1,
                     _ => 0,
@@ -65,20 +96,28 @@ impl Searcher<'_> {
 
                 let reduction = match () {
                     _ if extension > 0 => -extension,
+                    _ if !this.pruning.contains(Pruning::LMR) => 0,
+                    _ if !position.board.checkers().is_empty() => 0,
                     _ if position.is_capture(mv) => 0,
                     _ if !new_pos.board.checkers().is_empty() => 0,
-                    _ => pv_lmr(depth, i),
+                    _ if stage.is_refutation() => 0,
+                    _ if stage == MoveStage::Quiet && score > history_good_threshold() => {
+                        (pv_lmr(depth, i) - 1).clamp(0, depth - 1)
+                    }
+                    _ => pv_lmr(depth, i).clamp(0, depth - 1),
                 };
+                trace!("move", position, depth, window, "move" => mv, "reduction" => reduction);
 
-                let mut v =
-                    -this.visit_null(new_pos, -Window::null(window.lb()), depth - reduction - 1)?;
+                // a zero window search of a PV node's child is expected to fail high
+                let null_window = -Window::null(window.lb());
+                let mut v = -this.visit_null(new_pos, null_window, depth - reduction - 1, true)?;
 
                 if window.fail_low(v) {
                     return Some(v);
                 }
 
                 if reduction > 0 {
-                    v = -this.visit_null(new_pos, -Window::null(window.lb()), depth - 1)?;
+                    v = -this.visit_null(new_pos, null_window, depth - 1, true)?;
                     if window.fail_low(v) {
                         return Some(v);
                     }
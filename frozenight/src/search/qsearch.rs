@@ -1,14 +1,15 @@
 use std::sync::atomic::Ordering;
 
-use cozy_chess::{get_king_moves, BitBoard, Move, Piece, Rank};
+use cozy_chess::{get_king_moves, BitBoard, Move, Piece, Rank, Square};
 
 use crate::position::Position;
 use crate::tt::{NodeKind, TableEntry};
 use crate::Eval;
 
-use super::see::static_exchange_eval;
+use super::params::*;
+use super::see::{piece_value, static_exchange_eval};
 use super::window::Window;
-use super::{Searcher, INVALID_MOVE};
+use super::{Pruning, Searcher, INVALID_MOVE};
 
 impl Searcher<'_> {
     pub fn qsearch(&mut self, position: &Position, orig_window: Window) -> Eval {
@@ -55,13 +56,40 @@ impl Searcher<'_> {
             }
         }
 
+        // delta pruning: give up if even winning a queen can't raise alpha. this is unsafe in
+        // endgames and when we might promote, so skip it there.
+        let non_pawns = position.board.occupied()
+            & !position.board.pieces(Piece::Pawn)
+            & !position.board.pieces(Piece::King);
+        let delta_pruning =
+            !in_check && non_pawns.len() > 2 && self.pruning.contains(Pruning::DELTA);
+        let see_pruning = !in_check && self.pruning.contains(Pruning::SEE);
+        let our_promoters = position.board.pieces(Piece::Pawn)
+            & position.board.colors(us)
+            & Rank::Seventh.relative_to(us).bitboard();
+        if delta_pruning
+            && our_promoters.is_empty()
+            && window.fail_low(best + delta_margin(Piece::Queen))
+        {
+            return best;
+        }
+
+        // pawns may also capture en passant onto an empty square
+        let en_passant = match position.board.en_passant() {
+            Some(file) => Square::new(file, Rank::Sixth.relative_to(us)).bitboard(),
+            None => BitBoard::EMPTY,
+        };
+
         let mut moves = Vec::with_capacity(16);
         let mut had_moves = false;
         position.board.generate_moves_for(do_for, |mut mvs| {
             let promo =
                 mvs.piece == Piece::Pawn && mvs.from.rank() == Rank::Seventh.relative_to(us);
             if !promo {
-                mvs.to &= permitted;
+                mvs.to &= match mvs.piece {
+                    Piece::Pawn => permitted | en_passant,
+                    _ => permitted,
+                };
             }
             had_moves = true;
             for to in mvs.to {
@@ -70,11 +98,16 @@ impl Searcher<'_> {
                     to,
                     promotion: promo.then(|| Piece::Queen),
                 };
-                if position.is_capture(mv) {
-                    let victim = position.board.piece_on(mv.to).unwrap();
+                if let Some(victim) = position.capture_victim(mv) {
+                    if delta_pruning
+                        && mv.promotion.is_none()
+                        && window.fail_low(best + delta_margin(victim))
+                    {
+                        continue;
+                    }
                     let mvv_lva = 8 * victim as i32 - mvs.piece as i32 + 8;
                     let see = static_exchange_eval(&position.board, mv);
-                    if see >= 0 || in_check {
+                    if see >= QS_SEE_THRESHOLD.get() as i32 || !see_pruning {
                         moves.push((mv, see + mvv_lva));
                     }
                 } else {
@@ -95,7 +128,7 @@ impl Searcher<'_> {
                     had_moves = true;
                     if position.board.occupied().has(mv.to) {
                         let see = static_exchange_eval(&position.board, mv);
-                        if see >= 0 {
+                        if see >= QS_SEE_THRESHOLD.get() as i32 || !see_pruning {
                             moves.push((mv, see));
                         }
                     } else {
@@ -118,7 +151,7 @@ impl Searcher<'_> {
             }
 
             if !had_moves {
-                return Eval::DRAW;
+                return self.draw_score(position);
             }
         }
 
@@ -170,3 +203,9 @@ impl Searcher<'_> {
         best
     }
 }
+
+/// How much capturing `victim` could plausibly raise the eval by.
+fn delta_margin(victim: Piece) -> i16 {
+    // SEE values are in centipawns; evals are in fifths of a centipawn
+    (5 * piece_value(victim)) as i16 + DELTA_MARGIN.get()
+}
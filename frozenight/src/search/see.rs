@@ -1,13 +1,26 @@
 use cozy_chess::{
-    get_bishop_moves, get_king_moves, get_knight_moves, get_pawn_attacks, get_rook_moves, BitBoard,
-    Board, Move, Piece, Square,
+    get_between_rays, get_bishop_moves, get_bishop_rays, get_king_moves, get_knight_moves,
+    get_line_rays, get_pawn_attacks, get_rook_moves, get_rook_rays, BitBoard, Board, Color, Move,
+    Piece, Square,
 };
 
+use crate::position::capture_victim;
+
 const VALUES: [i32; Piece::NUM] = [100, 300, 325, 500, 900, 9999999];
 
+/// Material value of a piece in centipawns, as used by SEE.
+pub fn piece_value(piece: Piece) -> i32 {
+    VALUES[piece as usize]
+}
+
 pub fn static_exchange_eval(board: &Board, capture: Move) -> i32 {
-    let occupied = board.occupied() & !capture.from.bitboard();
-    VALUES[board.piece_on(capture.to).unwrap() as usize]
+    let mut occupied = board.occupied() & !capture.from.bitboard();
+    let victim = capture_victim(board, capture);
+    if victim.is_some() && !board.occupied().has(capture.to) {
+        // en passant; the captured pawn is beside the capturing one
+        occupied &= !Square::new(capture.to.file(), capture.from.rank()).bitboard();
+    }
+    victim.map_or(0, piece_value)
         - see_impl(
             board,
             capture.to,
@@ -20,6 +33,7 @@ fn see_impl(board: &Board, sq: Square, mut piece: Piece, mut occupied: BitBoard)
     let mut stm = board.side_to_move();
     let mut bishop_attacks = get_bishop_moves(sq, occupied);
     let mut rook_attacks = get_rook_moves(sq, occupied);
+    let pinned = pinned_away_from(board, sq);
 
     let mut gains = [0; 32];
     let mut index = 0;
@@ -27,7 +41,7 @@ fn see_impl(board: &Board, sq: Square, mut piece: Piece, mut occupied: BitBoard)
         index = i;
 
         stm = !stm;
-        let movable = board.colors(stm) & occupied;
+        let movable = board.colors(stm) & occupied & !pinned;
 
         if let Some(from) =
             (get_pawn_attacks(sq, !stm) & board.pieces(Piece::Pawn) & movable).next_square()
@@ -108,3 +122,62 @@ fn see_impl(board: &Board, sq: Square, mut piece: Piece, mut occupied: BitBoard)
 
     value
 }
+
+/// Pieces that are pinned to their king along a line which does not pass through `sq`, and
+/// therefore cannot take part in an exchange on `sq`.
+fn pinned_away_from(board: &Board, sq: Square) -> BitBoard {
+    let diagonal = board.pieces(Piece::Bishop) | board.pieces(Piece::Queen);
+    let orthogonal = board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+
+    let mut pinned = BitBoard::EMPTY;
+    for color in Color::ALL {
+        let king = board.king(color);
+        let snipers = ((get_bishop_rays(king) & diagonal) | (get_rook_rays(king) & orthogonal))
+            & board.colors(!color);
+        for sniper in snipers {
+            let blockers = get_between_rays(sniper, king) & board.occupied();
+            if blockers.len() == 1 && !get_line_rays(sniper, king).has(sq) {
+                pinned |= blockers & board.colors(color);
+            }
+        }
+    }
+    pinned
+}
+
+#[cfg(test)]
+mod tests {
+    use cozy_chess::Board;
+
+    use super::static_exchange_eval;
+
+    fn see(fen: &str, mv: &str) -> i32 {
+        let board: Board = fen.parse().unwrap();
+        static_exchange_eval(&board, mv.parse().unwrap())
+    }
+
+    #[test]
+    fn defended_pawn() {
+        assert_eq!(see("4k3/3n4/8/4p3/8/5N2/8/4K3 w - - 0 1", "f3e5"), -200);
+    }
+
+    #[test]
+    fn pinned_recapturer() {
+        // the knight on d7 defends e5, but is pinned to its king by the bishop on b5
+        assert_eq!(see("4k3/3n4/8/1B2p3/8/5N2/8/4K3 w - - 0 1", "f3e5"), 100);
+    }
+
+    #[test]
+    fn defended_by_rook() {
+        assert_eq!(see("3rk3/8/8/3p4/8/8/3R4/4K3 w - - 0 1", "d2d5"), -400);
+    }
+
+    #[test]
+    fn x_ray_rook() {
+        assert_eq!(see("3rk3/8/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5"), 100);
+    }
+
+    #[test]
+    fn x_ray_queen() {
+        assert_eq!(see("3rk3/8/8/3p4/8/8/3R4/3QK3 w - - 0 1", "d2d5"), 100);
+    }
+}
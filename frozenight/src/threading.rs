@@ -6,10 +6,13 @@ use std::time::Instant;
 
 use cozy_chess::{Board, Move};
 
-use crate::search::{AbdadaTable, INVALID_MOVE};
+use crate::search::AbdadaTable;
 use crate::time::{TimeConstraint, TimeManager};
 use crate::tt::TranspositionTable;
-use crate::{update_position, Eval, Frozenight, SearchInfo, SharedState, Statistics};
+use crate::{
+    legal_searchmoves, update_position, CurrmoveHandler, Frozenight, PositionError, Pruning,
+    SearchInfo, SharedState, Statistics,
+};
 
 pub struct MtFrozenight {
     board: Board,
@@ -17,6 +20,14 @@ pub struct MtFrozenight {
     shared_state: Arc<RwLock<SharedState>>,
     threads: Vec<(Arc<Statistics>, Sender<ThreadCommand>)>,
     abort: Arc<AtomicBool>,
+    multipv: usize,
+    contempt: i16,
+    pruning: Pruning,
+    repetition_count: u8,
+    searchmoves: Vec<Move>,
+    currmove: Option<CurrmoveHandler>,
+    ponder: bool,
+    search_state: Option<Arc<Mutex<MtSyncState>>>,
 }
 
 enum ThreadCommand {
@@ -25,6 +36,12 @@ enum ThreadCommand {
         multithreaded: bool,
         max_nodes: u64,
         max_depth: i16,
+        multipv: usize,
+        contempt: i16,
+        pruning: Pruning,
+        repetition_count: u8,
+        searchmoves: Vec<Move>,
+        currmove: Option<CurrmoveHandler>,
         deadline: Option<Instant>,
         state: Arc<Mutex<MtSyncState>>,
         abort: Arc<AtomicBool>,
@@ -38,6 +55,10 @@ struct MtSyncState {
     info: Box<dyn FnMut(&SearchInfo) + Send>,
     finish: Option<Box<dyn FnOnce(&SearchInfo) + Send>>,
     stats: Vec<Arc<Statistics>>,
+    /// While pondering, the time constraint to switch to on ponderhit.
+    ponder_time: Option<TimeConstraint>,
+    /// Set if the threads stopped while pondering, so `finish` is waiting for ponderhit or abort.
+    stopped: bool,
 }
 
 impl MtFrozenight {
@@ -48,9 +69,19 @@ impl MtFrozenight {
             shared_state: Arc::new(RwLock::new(SharedState {
                 tt: TranspositionTable::new(hash_mb),
                 abdada: AbdadaTable::new(),
+                #[cfg(feature = "syzygy")]
+                tb: crate::Tablebase::new(),
             })),
             threads: vec![],
             abort: Default::default(),
+            multipv: 1,
+            contempt: 0,
+            pruning: Pruning::ALL,
+            repetition_count: 2,
+            searchmoves: vec![],
+            currmove: None,
+            ponder: false,
+            search_state: None,
         };
         this.set_threads(1);
         this
@@ -83,6 +114,53 @@ impl MtFrozenight {
         state.tt = TranspositionTable::new(hash_mb);
     }
 
+    #[cfg(feature = "syzygy")]
+    pub fn set_tablebase(&mut self, tb: crate::Tablebase) {
+        self.abort();
+        self.shared_state.write().unwrap().tb = tb;
+    }
+
+    pub fn set_multipv(&mut self, multipv: usize) {
+        self.multipv = multipv;
+    }
+
+    /// Sets how many centipawns worse than even the engine considers a draw.
+    pub fn set_contempt(&mut self, contempt: i16) {
+        self.contempt = contempt;
+    }
+
+    /// Sets which pruning heuristics the search may use.
+    pub fn set_pruning(&mut self, pruning: Pruning) {
+        self.pruning = pruning;
+    }
+
+    /// Sets how many times a position must occur for the search to score it as a draw. Positions
+    /// from before the root always need to occur three times, since that is what the rules
+    /// require; with the default of 2, a position repeated inside the search is a draw already.
+    pub fn set_repetition_count(&mut self, count: u8) {
+        self.repetition_count = count;
+    }
+
+    /// Restricts the next searches to the given root moves of the current position. An empty list
+    /// searches all moves. Duplicates and illegal moves are dropped, but it is an error if none of
+    /// the moves are legal.
+    pub fn set_searchmoves(&mut self, moves: Vec<Move>) -> Result<(), PositionError> {
+        let legal = legal_searchmoves(&self.board, &moves);
+        self.searchmoves = legal.clone().unwrap_or_default();
+        legal.map(drop)
+    }
+
+    /// Sets a function to call with each root move and its number as the search starts on it.
+    pub fn set_currmove_handler(&mut self, f: impl Fn(Move, usize) + Send + Sync + 'static) {
+        self.currmove = Some(Arc::new(f));
+    }
+
+    /// Makes the next searches ponder: they ignore the clock, and don't report the best move
+    /// until `ponderhit` or `abort`, even if they stop by themselves.
+    pub fn set_ponder(&mut self, ponder: bool) {
+        self.ponder = ponder;
+    }
+
     pub fn set_position(&mut self, position: Board, moves: impl Iterator<Item = Move>) {
         self.abort();
         let mut new = position;
@@ -106,6 +184,45 @@ impl MtFrozenight {
 
     pub fn abort(&mut self) {
         self.abort.store(true, Ordering::Relaxed);
+        if let Some(state) = &self.search_state {
+            let mut state = state.lock().unwrap();
+            state.ponder_time = None;
+            if state.stopped {
+                if let Some(finish) = state.finish.take() {
+                    finish(&state.recent_info);
+                }
+            }
+        }
+    }
+
+    /// Switches a pondering search over to its time constraint, as if it had been started
+    /// normally.
+    pub fn ponderhit(&mut self) {
+        let state = match &self.search_state {
+            Some(state) => state,
+            None => return,
+        };
+        let mut state = state.lock().unwrap();
+        let time = match state.ponder_time.take() {
+            Some(time) => time,
+            None => return,
+        };
+        if state.stopped {
+            if let Some(finish) = state.finish.take() {
+                finish(&state.recent_info);
+            }
+            return;
+        }
+
+        state.tm = TimeManager::new(&self.board, time);
+        if let Some(deadline) = state.tm.deadline() {
+            // the threads were started without a deadline, so enforce it from outside
+            let abort = self.abort.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+                abort.store(true, Ordering::Relaxed);
+            });
+        }
     }
 
     pub fn search(
@@ -122,23 +239,33 @@ impl MtFrozenight {
             .iter()
             .map(|(stats, _)| stats.clone())
             .collect();
-        let tm = TimeManager::new(&self.board, time);
+        let ponder_time = match self.ponder {
+            true => Some(time),
+            false => None,
+        };
+        let tm = match self.ponder {
+            true => TimeManager::new(
+                &self.board,
+                TimeConstraint {
+                    clock: None,
+                    ..time
+                },
+            ),
+            false => TimeManager::new(&self.board, time),
+        };
         let mut deadline = tm.deadline();
+        let mut currmove = self.currmove.clone();
 
         let state = Arc::new(Mutex::new(MtSyncState {
-            recent_info: SearchInfo {
-                eval: Eval::DRAW,
-                nodes: 0,
-                depth: 0,
-                selective_depth: 0,
-                best_move: INVALID_MOVE,
-                pv: vec![],
-            },
+            recent_info: SearchInfo::initial(&self.board),
             tm,
             info: Box::new(info),
             finish: Some(Box::new(finish)),
             stats,
+            ponder_time,
+            stopped: false,
         }));
+        self.search_state = Some(state.clone());
 
         let multithreaded = self.threads.len() > 1;
         for (_, sender) in &self.threads {
@@ -146,6 +273,12 @@ impl MtFrozenight {
                 multithreaded,
                 max_nodes: time.nodes,
                 max_depth: time.depth,
+                multipv: self.multipv,
+                contempt: self.contempt,
+                pruning: self.pruning,
+                repetition_count: self.repetition_count,
+                searchmoves: self.searchmoves.clone(),
+                currmove: currmove.take(),
                 deadline: deadline.take(),
                 state: state.clone(),
                 abort: self.abort.clone(),
@@ -168,17 +301,29 @@ fn run_thread(mut engine: Frozenight, recv: Receiver<ThreadCommand>) {
                 multithreaded,
                 max_nodes,
                 max_depth,
+                multipv,
+                contempt,
+                pruning,
+                repetition_count,
+                searchmoves,
+                currmove,
                 deadline,
                 state,
                 abort,
             } => {
+                engine.contempt = contempt;
+                engine.pruning = pruning;
+                engine.repetition_count = repetition_count;
+                engine.searchmoves = searchmoves;
+                engine.currmove = currmove;
                 engine.search_internal(
                     max_depth,
                     max_nodes,
+                    multipv,
                     &abort,
                     multithreaded,
                     deadline,
-                    |depth, searcher, mv, eval| {
+                    |depth, searcher, lines, bound| {
                         let mut state = state.lock().unwrap();
                         let state = &mut *state;
                         if depth <= state.recent_info.depth {
@@ -194,14 +339,27 @@ fn run_thread(mut engine: Frozenight, recv: Receiver<ThreadCommand>) {
                                 selective_depth.max(stats.selective_depth.load(Ordering::Relaxed));
                         }
 
-                        state.recent_info = SearchInfo {
+                        let (best_move, eval) = lines[0];
+                        let info = SearchInfo {
                             eval,
                             depth,
                             selective_depth,
                             nodes,
-                            best_move: mv,
-                            pv: searcher.extract_pv(depth),
+                            hashfull: searcher.shared.tt.hashfull(),
+                            best_move,
+                            pv: searcher.extract_pv(best_move, depth),
+                            other_pvs: lines[1..]
+                                .iter()
+                                .map(|&(mv, eval)| (eval, searcher.extract_pv(mv, depth)))
+                                .collect(),
+                            root_node_counts: searcher.root_node_totals.clone(),
+                            bound,
                         };
+                        if bound.is_some() {
+                            (state.info)(&info);
+                            return ControlFlow::Continue(());
+                        }
+                        state.recent_info = info;
                         (state.info)(&state.recent_info);
                         state.tm.update(&state.recent_info)
                     },
@@ -209,10 +367,109 @@ fn run_thread(mut engine: Frozenight, recv: Receiver<ThreadCommand>) {
 
                 abort.store(true, Ordering::Relaxed);
                 let mut state = state.lock().unwrap();
-                if let Some(finish) = state.finish.take() {
+                if state.ponder_time.is_some() {
+                    state.stopped = true;
+                } else if let Some(finish) = state.finish.take() {
                     finish(&state.recent_info);
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{RecvTimeoutError, TryRecvError};
+    use std::time::Duration;
+
+    use super::*;
+
+    /// Starts a pondering search from the start position, sending the best move to the returned
+    /// receiver each time `finish` is called.
+    fn ponder(engine: &mut MtFrozenight, time: TimeConstraint) -> Receiver<Move> {
+        let (sender, recv) = channel();
+        engine.set_ponder(true);
+        engine.search(
+            time,
+            |_| {},
+            move |info| {
+                let _ = sender.send(info.best_move);
+            },
+        );
+        recv
+    }
+
+    #[test]
+    fn ponderhit_finishes_once_within_hard_limit() {
+        let mut engine = MtFrozenight::new(1);
+        // not using all the time, so the hard limit is half the clock
+        let clock = Duration::from_millis(400);
+        let recv = ponder(
+            &mut engine,
+            TimeConstraint {
+                clock: Some(clock),
+                use_all_time: false,
+                ..TimeConstraint::INFINITE
+            },
+        );
+
+        // the clock is ignored while pondering
+        std::thread::sleep(clock);
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
+
+        let ponderhit = Instant::now();
+        engine.ponderhit();
+        let mv = recv.recv_timeout(clock).unwrap();
+        assert!(ponderhit.elapsed() < clock, "{:?}", ponderhit.elapsed());
+        assert!(engine.board().is_legal(mv));
+
+        engine.abort();
+        assert_eq!(
+            recv.recv_timeout(Duration::from_millis(100)),
+            Err(RecvTimeoutError::Timeout)
+        );
+    }
+
+    #[test]
+    fn ponderhit_after_stopping_finishes_immediately() {
+        let mut engine = MtFrozenight::new(1);
+        let recv = ponder(
+            &mut engine,
+            TimeConstraint {
+                depth: 1,
+                ..TimeConstraint::INFINITE
+            },
+        );
+
+        // the search stops by itself, but holds back the result until ponderhit
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(recv.try_recv(), Err(TryRecvError::Empty));
+
+        engine.ponderhit();
+        let mv = recv.try_recv().unwrap();
+        assert!(engine.board().is_legal(mv));
+
+        engine.abort();
+        assert_eq!(
+            recv.recv_timeout(Duration::from_millis(100)),
+            Err(RecvTimeoutError::Timeout)
+        );
+    }
+
+    #[test]
+    fn abort_after_stopping_flushes_the_result() {
+        let mut engine = MtFrozenight::new(1);
+        let recv = ponder(
+            &mut engine,
+            TimeConstraint {
+                depth: 1,
+                ..TimeConstraint::INFINITE
+            },
+        );
+
+        std::thread::sleep(Duration::from_millis(200));
+        engine.abort();
+        let mv = recv.try_recv().unwrap();
+        assert!(engine.board().is_legal(mv));
+    }
+}
@@ -1,7 +1,7 @@
 use std::ops::ControlFlow;
 use std::time::{Duration, Instant};
 
-use cozy_chess::Board;
+use cozy_chess::{Board, Move};
 
 use crate::SearchInfo;
 
@@ -14,6 +14,11 @@ pub struct TimeConstraint {
     pub overhead: Duration,
     pub moves_to_go: Option<u32>,
     pub use_all_time: bool,
+    /// With a clock, spend at least this long on a move unless it is the only legal one. This
+    /// never goes past the hard limit, so it can't cause a loss on time. It only raises the soft
+    /// limit: a search that reaches its depth or node limit still stops then, and positions the
+    /// tablebases resolve get no exemption.
+    pub min_time: Duration,
 }
 
 impl TimeConstraint {
@@ -25,24 +30,34 @@ impl TimeConstraint {
         overhead: Duration::ZERO,
         moves_to_go: None,
         use_all_time: true,
+        min_time: Duration::ZERO,
     };
 }
 
 pub(crate) struct TimeManager {
-    soft_deadline: Option<Instant>,
+    start: Instant,
+    soft_limit: Option<Duration>,
     hard_deadline: Option<Instant>,
     one_reply: bool,
+    last_best_move: Option<Move>,
 }
 
 impl TimeManager {
     pub fn new(board: &Board, time: TimeConstraint) -> Self {
         let now = Instant::now();
+        let hard_limit = time.clock.map(|clock| {
+            // with a fixed move time we may use all of it, otherwise keep some in reserve
+            let limit = match time.use_all_time {
+                true => clock,
+                false => clock / 2,
+            };
+            limit.saturating_sub(time.overhead)
+        });
         TimeManager {
+            start: now,
             one_reply: !time.use_all_time && time.clock.is_some() && one_reply(board),
-            hard_deadline: time
-                .clock
-                .map(|clock| now + (clock / 2).saturating_sub(time.overhead)),
-            soft_deadline: time
+            hard_deadline: hard_limit.map(|limit| now + limit),
+            soft_limit: time
                 .clock
                 .map(|clock| {
                     if time.use_all_time {
@@ -53,7 +68,10 @@ impl TimeManager {
 
                     clock.saturating_sub(time.increment) / mtg + time.increment / 2
                 })
-                .map(|amt| now + (amt.saturating_sub(time.overhead))),
+                .map(|amt| amt.saturating_sub(time.overhead))
+                .zip(hard_limit)
+                .map(|(soft, hard)| soft.max(time.min_time).min(hard)),
+            last_best_move: None,
         }
     }
 
@@ -61,12 +79,20 @@ impl TimeManager {
         self.hard_deadline
     }
 
-    pub fn update(&mut self, _info: &SearchInfo) -> ControlFlow<()> {
-        match self.soft_deadline {
+    pub fn update(&mut self, info: &SearchInfo) -> ControlFlow<()> {
+        let previous_best_move = self.last_best_move.replace(info.best_move);
+        let best_move_changed = matches!(previous_best_move, Some(mv) if mv != info.best_move);
+
+        match self.soft_limit {
             _ if self.one_reply => ControlFlow::Break(()),
             None => ControlFlow::Continue(()),
-            Some(deadline) => {
-                if Instant::now() < deadline {
+            Some(limit) => {
+                // spend a bit longer when the best move is still changing
+                let limit = match best_move_changed {
+                    true => limit * 3 / 2,
+                    false => limit,
+                };
+                if self.start.elapsed() < limit {
                     ControlFlow::Continue(())
                 } else {
                     ControlFlow::Break(())
@@ -84,3 +110,144 @@ fn one_reply(board: &Board) -> bool {
     });
     moves == 1
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(secs: u64) -> TimeConstraint {
+        TimeConstraint {
+            clock: Some(Duration::from_secs(secs)),
+            use_all_time: false,
+            ..TimeConstraint::INFINITE
+        }
+    }
+
+    fn limits(time: TimeConstraint) -> (Option<Duration>, Option<Duration>) {
+        let tm = TimeManager::new(&Board::default(), time);
+        let hard = tm.deadline().map(|d| d.duration_since(tm.start));
+        (tm.soft_limit, hard)
+    }
+
+    fn ms(ms: u64) -> Option<Duration> {
+        Some(Duration::from_millis(ms))
+    }
+
+    #[test]
+    fn sudden_death() {
+        assert_eq!(limits(clock(60)), (ms(1200), ms(30000)));
+    }
+
+    #[test]
+    fn increment() {
+        let time = TimeConstraint {
+            increment: Duration::from_secs(1),
+            ..clock(60)
+        };
+        assert_eq!(limits(time), (ms(1680), ms(30000)));
+    }
+
+    #[test]
+    fn moves_to_go() {
+        let time = TimeConstraint {
+            moves_to_go: Some(10),
+            ..clock(60)
+        };
+        assert_eq!(limits(time), (ms(4000), ms(30000)));
+    }
+
+    #[test]
+    fn increment_and_moves_to_go() {
+        let time = TimeConstraint {
+            increment: Duration::from_secs(1),
+            moves_to_go: Some(10),
+            ..clock(60)
+        };
+        let soft = Duration::from_secs(59) / 15 + Duration::from_millis(500);
+        assert_eq!(limits(time), (Some(soft), ms(30000)));
+    }
+
+    #[test]
+    fn overhead() {
+        let time = TimeConstraint {
+            overhead: Duration::from_millis(100),
+            ..clock(60)
+        };
+        assert_eq!(limits(time), (ms(1100), ms(29900)));
+    }
+
+    #[test]
+    fn soft_limit_never_exceeds_hard_limit() {
+        let time = TimeConstraint {
+            increment: Duration::from_secs(10),
+            ..clock(1)
+        };
+        assert_eq!(limits(time), (ms(500), ms(500)));
+    }
+
+    #[test]
+    fn min_time_raises_soft_limit() {
+        let time = TimeConstraint {
+            min_time: Duration::from_secs(5),
+            ..clock(60)
+        };
+        assert_eq!(limits(time), (ms(5000), ms(30000)));
+
+        // but not past the hard limit
+        let time = TimeConstraint {
+            min_time: Duration::from_secs(60),
+            ..clock(60)
+        };
+        assert_eq!(limits(time), (ms(30000), ms(30000)));
+
+        // and never lowers it
+        let time = TimeConstraint {
+            min_time: Duration::from_millis(100),
+            ..clock(60)
+        };
+        assert_eq!(limits(time), (ms(1200), ms(30000)));
+    }
+
+    #[test]
+    fn move_time() {
+        let time = TimeConstraint {
+            use_all_time: true,
+            overhead: Duration::from_millis(100),
+            ..clock(5)
+        };
+        assert_eq!(limits(time), (ms(4900), ms(4900)));
+    }
+
+    #[test]
+    fn no_clock() {
+        assert_eq!(limits(TimeConstraint::INFINITE), (None, None));
+    }
+
+    #[test]
+    fn changing_best_move_extends_soft_limit() {
+        let board = Board::default();
+        let mut tm = TimeManager::new(&board, clock(60));
+        // pretend we are 20% past the soft limit, which is within the 50% extension
+        tm.start -= tm.soft_limit.unwrap() * 6 / 5;
+
+        let mut info = SearchInfo::initial(&board);
+        info.best_move = "e2e4".parse().unwrap();
+        assert_eq!(tm.update(&info), ControlFlow::Break(()));
+        info.best_move = "d2d4".parse().unwrap();
+        assert_eq!(tm.update(&info), ControlFlow::Continue(()));
+        assert_eq!(tm.update(&info), ControlFlow::Break(()));
+    }
+
+    #[test]
+    fn one_reply_stops_immediately_despite_min_time() {
+        let board: Board = "k7/8/8/8/8/8/1q6/K7 w - - 0 1".parse().unwrap();
+        let time = TimeConstraint {
+            min_time: Duration::from_secs(5),
+            ..clock(60)
+        };
+        let mut tm = TimeManager::new(&board, time);
+        let mut info = SearchInfo::initial(&board);
+        info.best_move = "a1b2".parse().unwrap();
+        assert_eq!(tm.update(&info), ControlFlow::Break(()));
+    }
+}
@@ -7,54 +7,49 @@ use crate::position::Position;
 use crate::Eval;
 
 pub struct TranspositionTable {
-    entries: Box<[TtEntry]>,
+    buckets: Box<[TtBucket]>,
     search_number: u8,
 }
 
-const ENTRIES_PER_MB: usize = 1024 * 1024 / std::mem::size_of::<TtEntry>();
+const BUCKETS_PER_MB: usize = 1024 * 1024 / std::mem::size_of::<TtBucket>();
 
 impl TranspositionTable {
     pub fn new(hash_mb: usize) -> Self {
         assert!(hash_mb > 0);
         TranspositionTable {
-            entries: (0..hash_mb * ENTRIES_PER_MB)
-                .map(|_| TtEntry::default())
+            buckets: (0..hash_mb * BUCKETS_PER_MB)
+                .map(|_| TtBucket::default())
                 .collect(),
             search_number: 2,
         }
     }
 
-    fn entry(&self, hash: u64) -> &TtEntry {
+    fn bucket(&self, hash: u64) -> &TtBucket {
         unsafe {
-            // SAFETY: This is a fixed-point multiply of `self.entries.len()` by hash/2^64.
+            // SAFETY: This is a fixed-point multiply of `self.buckets.len()` by hash/2^64.
             //         Since `hash` is in 0..1 and does not include 1, the result cannot overflow
-            //         and also cannot exceed `self.entries.len()` and therefore is in-bounds.
-            let index = hash as u128 * self.entries.len() as u128 >> 64;
-            self.entries.get_unchecked(index as usize)
+            //         and also cannot exceed `self.buckets.len()` and therefore is in-bounds.
+            let index = hash as u128 * self.buckets.len() as u128 >> 64;
+            self.buckets.get_unchecked(index as usize)
         }
     }
 
+    fn find(&self, hash: u64) -> Option<TtData> {
+        self.bucket(hash).entries.iter().find_map(|entry| {
+            let data = entry.data.load(Ordering::Relaxed);
+            let hxd = entry.hash.load(Ordering::Relaxed);
+            (hxd ^ data == hash).then(|| bytemuck::cast(data))
+        })
+    }
+
     pub fn get_move(&self, board: &Board) -> Option<Move> {
-        let entry = self.entry(board.hash());
-        let data = entry.data.load(Ordering::Relaxed);
-        let hxd = entry.hash.load(Ordering::Relaxed);
-        if hxd ^ data != board.hash() {
-            return None;
-        }
-        let data: TtData = bytemuck::cast(data);
-        data.unmarshall_move(board)
+        self.find(board.hash())?.unmarshall_move(board)
     }
 
     pub fn get(&self, position: &Position) -> Option<TableEntry> {
-        let entry = self.entry(position.board.hash());
-        let data = entry.data.load(Ordering::Relaxed);
-        let hxd = entry.hash.load(Ordering::Relaxed);
-        if hxd ^ data != position.board.hash() {
-            return None;
-        }
         // marshal between usable type and stored data
         // also validates the data
-        let data: TtData = bytemuck::cast(data);
+        let data = self.find(position.board.hash())?;
 
         let kind = match data.kind {
             0 => NodeKind::Exact,
@@ -78,29 +73,49 @@ impl TranspositionTable {
         unsafe {
             use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
             _mm_prefetch(
-                self.entry(board.hash()) as *const _ as *const _,
+                self.bucket(board.hash()) as *const _ as *const _,
                 _MM_HINT_T0,
             );
         }
     }
 
     pub fn store(&self, position: &Position, data: TableEntry) {
-        let entry = self.entry(position.board.hash());
-
-        let old_data = entry.data.load(Ordering::Relaxed);
-        let old_hash = entry.hash.load(Ordering::Relaxed) ^ old_data;
-        let old_data: TtData = bytemuck::cast(old_data);
-
-        let mut replace = false;
-        // always replace existing position data with PV data
-        replace |= old_hash == position.board.hash() && data.kind == NodeKind::Exact;
-        // prefer deeper data
-        replace |= data.depth >= old_data.depth;
-        // prefer replacing stale data
-        replace |= self.search_number.wrapping_sub(old_data.age) >= 2;
-
-        if !replace {
-            return;
+        let hash = position.board.hash();
+        let bucket = self.bucket(hash);
+
+        // pick the slot holding this position if there is one, otherwise the least valuable slot,
+        // preferring stale data and then shallower data
+        let mut slot = &bucket.entries[0];
+        let mut slot_priority = (true, i16::MAX);
+        for entry in &bucket.entries {
+            let old_data = entry.data.load(Ordering::Relaxed);
+            let old_hash = entry.hash.load(Ordering::Relaxed) ^ old_data;
+            let old_data: TtData = bytemuck::cast(old_data);
+
+            if old_hash == hash {
+                let mut replace = false;
+                // always replace existing position data with PV data
+                replace |= data.kind == NodeKind::Exact;
+                // prefer deeper data
+                replace |= data.depth >= old_data.depth;
+                // prefer replacing stale data
+                replace |= self.search_number.wrapping_sub(old_data.age) >= 2;
+
+                if !replace {
+                    return;
+                }
+                slot = entry;
+                break;
+            }
+
+            let priority = (
+                self.search_number.wrapping_sub(old_data.age) < 2,
+                old_data.depth,
+            );
+            if priority < slot_priority {
+                slot = entry;
+                slot_priority = priority;
+            }
         }
 
         let promo = match data.mv.promotion {
@@ -118,10 +133,24 @@ impl TranspositionTable {
             kind: data.kind as u8,
             age: self.search_number,
         });
-        entry.data.store(data, Ordering::Relaxed);
-        entry
-            .hash
-            .store(position.board.hash() ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+        slot.hash.store(hash ^ data, Ordering::Relaxed);
+    }
+
+    /// Estimates how full the table is with data from the current search, in permille.
+    pub fn hashfull(&self) -> u16 {
+        let sample = &self.buckets[..self.buckets.len().min(250)];
+        let used = sample
+            .iter()
+            .flat_map(|bucket| &bucket.entries)
+            .filter(|entry| {
+                let data = entry.data.load(Ordering::Relaxed);
+                let hash = entry.hash.load(Ordering::Relaxed);
+                let data: TtData = bytemuck::cast(data);
+                hash != 0 && data.age == self.search_number
+            })
+            .count();
+        (used * 1000 / (sample.len() * 4)) as u16
     }
 
     pub fn increment_age(&mut self, by: u8) {
@@ -144,6 +173,12 @@ pub enum NodeKind {
     UpperBound,
 }
 
+#[derive(Default)]
+#[repr(align(64))]
+struct TtBucket {
+    entries: [TtEntry; 4],
+}
+
 #[derive(Default)]
 struct TtEntry {
     hash: AtomicU64,
@@ -178,3 +213,114 @@ impl TtData {
         board.is_legal(mv).then(|| mv)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A table with a single bucket, so every position collides.
+    fn one_bucket() -> TranspositionTable {
+        TranspositionTable {
+            buckets: vec![TtBucket::default()].into_boxed_slice(),
+            search_number: 2,
+        }
+    }
+
+    fn after(mv: &str) -> Position {
+        Position::from_root(Board::default()).play_move(mv.parse().unwrap())
+    }
+
+    fn entry(eval: Eval, depth: i16, kind: NodeKind) -> TableEntry {
+        TableEntry {
+            mv: "e7e5".parse().unwrap(),
+            eval,
+            depth,
+            kind,
+        }
+    }
+
+    fn assert_entry(tt: &TranspositionTable, position: &Position, expected: TableEntry) {
+        let found = tt.get(position).unwrap();
+        assert_eq!(found.mv, expected.mv);
+        assert_eq!(found.eval, expected.eval);
+        assert_eq!(found.depth, expected.depth);
+        assert_eq!(found.kind, expected.kind);
+    }
+
+    #[test]
+    fn exact_entry_round_trip() {
+        let tt = TranspositionTable::new(1);
+        let position = after("e2e4");
+        let stored = entry(Eval::new(-123), 7, NodeKind::Exact);
+        tt.store(&position, stored);
+        assert_entry(&tt, &position, stored);
+        assert_eq!(tt.get_move(&position.board), Some(stored.mv));
+        assert!(tt.get(&after("d2d4")).is_none());
+    }
+
+    #[test]
+    fn collisions_keep_neighbours_intact() {
+        let tt = one_bucket();
+        let moves = ["e2e4", "d2d4", "g1f3", "b1c3"];
+        for (i, mv) in moves.iter().enumerate() {
+            tt.store(
+                &after(mv),
+                entry(Eval::new(i as i16), 10 + i as i16, NodeKind::LowerBound),
+            );
+        }
+        for (i, mv) in moves.iter().enumerate() {
+            let expected = entry(Eval::new(i as i16), 10 + i as i16, NodeKind::LowerBound);
+            assert_entry(&tt, &after(mv), expected);
+        }
+
+        // a fifth position evicts the shallowest entry, and only that one
+        tt.store(
+            &after("c2c4"),
+            entry(Eval::new(50), 1, NodeKind::UpperBound),
+        );
+        assert_entry(
+            &tt,
+            &after("c2c4"),
+            entry(Eval::new(50), 1, NodeKind::UpperBound),
+        );
+        assert!(tt.get(&after("e2e4")).is_none());
+        for (i, mv) in moves.iter().enumerate().skip(1) {
+            let expected = entry(Eval::new(i as i16), 10 + i as i16, NodeKind::LowerBound);
+            assert_entry(&tt, &after(mv), expected);
+        }
+    }
+
+    #[test]
+    fn mate_scores_are_relative_to_the_probing_node() {
+        let tt = TranspositionTable::new(1);
+        let mut winning = after("e2e4");
+        // at ply 4, mate in 7 plies from the root is mate in 3 plies from this node
+        winning.ply = 4;
+        tt.store(&winning, entry(Eval::MATE.add_time(7), 5, NodeKind::Exact));
+        winning.ply = 0;
+        assert_eq!(tt.get(&winning).unwrap().eval, Eval::MATE.add_time(3));
+        winning.ply = 2;
+        assert_eq!(tt.get(&winning).unwrap().eval, Eval::MATE.add_time(5));
+
+        let mut losing = after("d2d4");
+        losing.ply = 0;
+        tt.store(&losing, entry(-Eval::MATE.add_time(7), 5, NodeKind::Exact));
+        losing.ply = 4;
+        assert_eq!(tt.get(&losing).unwrap().eval, -Eval::MATE.add_time(11));
+    }
+
+    #[test]
+    fn margin_on_probed_mate_score_does_not_overflow() {
+        let tt = TranspositionTable::new(1);
+        let position = after("e2e4");
+        tt.store(
+            &position,
+            entry(Eval::MATE.add_time(3), 5, NodeKind::LowerBound),
+        );
+
+        let eval = tt.get(&position).unwrap().eval;
+        assert_eq!(eval, Eval::MATE.add_time(3));
+        assert_eq!(eval + i16::MAX, Eval::MAX_INCONCLUSIVE);
+        assert_eq!(-eval - i16::MAX, -Eval::MAX_INCONCLUSIVE);
+    }
+}
@@ -0,0 +1,46 @@
+use frozenight::{parse_epd, to_san, Frozenight, TimeConstraint};
+
+/// Runs a test suite of EPD records with `bm`/`am` operations, searching each position for a
+/// fixed number of nodes. Usage: `epd <file> [nodes]`.
+pub fn epd(path: &str, nodes: u64) {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path, e);
+            return;
+        }
+    };
+
+    let mut positions = vec![];
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        match parse_epd(line) {
+            Ok(epd) => positions.push(epd),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    let mut engine = Frozenight::new(16);
+    let results = engine.analyze_epd(
+        &positions,
+        TimeConstraint {
+            nodes,
+            ..TimeConstraint::INFINITE
+        },
+    );
+
+    let mut passed = 0;
+    for (i, (result, epd)) in results.iter().zip(&positions).enumerate() {
+        let id = result.id.clone().unwrap_or_else(|| (i + 1).to_string());
+        let best_move = match epd.board.is_legal(result.best_move) {
+            true => to_san(&epd.board, result.best_move),
+            false => "none".to_owned(),
+        };
+        let status = match result.passed {
+            true => "pass",
+            false => "fail",
+        };
+        println!("{} {} {} {}", id, status, best_move, result.eval);
+        passed += result.passed as usize;
+    }
+    println!("{}/{} passed", passed, results.len());
+}
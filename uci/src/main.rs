@@ -1,22 +1,43 @@
 use std::io::{stdin, stdout, Write};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use cozy_chess::{Board, Color, File, Move, Piece, Square};
-use frozenight::{MtFrozenight, TimeConstraint};
+use cozy_chess::{Board, Color};
+use frozenight::{
+    from_uci_castling, to_uci_castling, Book, BookSelection, Bound, Eval, MtFrozenight, Pruning,
+    TimeConstraint,
+};
 
 mod bench;
+mod epd;
+mod perft;
 
 fn main() {
     if std::env::args().any(|arg| arg == "bench") {
         bench::bench();
         return;
     }
+    let args: Vec<_> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("epd") {
+        let nodes = args.get(3).and_then(|n| n.parse().ok()).unwrap_or(100_000);
+        match args.get(2) {
+            Some(path) => epd::epd(path, nodes),
+            None => eprintln!("usage: epd <file> [nodes]"),
+        }
+        return;
+    }
 
     let mut frozenight = MtFrozenight::new(32);
 
     let mut move_overhead = Duration::from_millis(0);
+    let mut min_time = Duration::from_millis(0);
     let mut ob_no_adj = false;
     let mut chess960 = false;
+    let mut show_wdl = false;
+    let mut white_relative = false;
+    let mut pruning = Pruning::ALL;
+    let mut own_book = false;
+    let mut book: Option<Book> = None;
+    let mut book_selection = BookSelection::WeightedRandom;
 
     let mut buf = String::new();
     loop {
@@ -42,10 +63,29 @@ fn main() {
                     );
                     println!("id author MinusKelvin <mark.carlson@minuskelvin.net>");
                     println!("option name Move Overhead type spin default 0 min 0 max 5000");
+                    println!(
+                        "option name Minimum Thinking Time type spin default 0 min 0 max 5000"
+                    );
                     println!("option name Hash type spin default 32 min 1 max 1048576");
                     println!("option name Threads type spin default 1 min 1 max 64");
                     println!("option name OB_noadj type check default false");
                     println!("option name UCI_Chess960 type check default false");
+                    println!("option name UCI_ShowWDL type check default false");
+                    println!("option name WhiteRelativeScore type check default false");
+                    println!("option name Ponder type check default false");
+                    println!("option name MultiPV type spin default 1 min 1 max 256");
+                    println!("option name Contempt type spin default 0 min -100 max 100");
+                    println!("option name RepetitionCount type spin default 2 min 2 max 3");
+                    println!("option name OwnBook type check default false");
+                    println!("option name BookFile type string default <empty>");
+                    println!(
+                        "option name BookSelection type combo default Random var Best var Random"
+                    );
+                    for (name, _) in Pruning::FLAGS {
+                        println!("option name Prune {} type check default true", name);
+                    }
+                    #[cfg(feature = "syzygy")]
+                    println!("option name SyzygyPath type string default <empty>");
                     #[cfg(feature = "tweakable")]
                     for param in frozenight::all_parameters() {
                         println!(
@@ -80,6 +120,9 @@ fn main() {
                         "Move Overhead" => {
                             move_overhead = Duration::from_millis(stream.next()?.parse().ok()?)
                         }
+                        "Minimum Thinking Time" => {
+                            min_time = Duration::from_millis(stream.next()?.parse().ok()?)
+                        }
                         "Hash" => {
                             frozenight.set_hash(stream.next()?.parse().ok()?);
                         }
@@ -89,9 +132,66 @@ fn main() {
                         "UCI_Chess960" => {
                             chess960 = stream.next()? == "true";
                         }
+                        "UCI_ShowWDL" => {
+                            show_wdl = stream.next()? == "true";
+                        }
+                        "WhiteRelativeScore" => {
+                            white_relative = stream.next()? == "true";
+                        }
                         "Threads" => {
                             frozenight.set_threads(stream.next()?.parse().ok()?);
                         }
+                        "MultiPV" => {
+                            frozenight.set_multipv(stream.next()?.parse().ok()?);
+                        }
+                        "Contempt" => {
+                            frozenight.set_contempt(stream.next()?.parse().ok()?);
+                        }
+                        "RepetitionCount" => {
+                            frozenight.set_repetition_count(stream.next()?.parse().ok()?);
+                        }
+                        _ if opt.starts_with("Prune ") => {
+                            let &(_, flag) = Pruning::FLAGS
+                                .iter()
+                                .find(|&&(name, _)| opt["Prune ".len()..] == *name)?;
+                            pruning.set(flag, stream.next()? == "true");
+                            frozenight.set_pruning(pruning);
+                        }
+                        "OwnBook" => {
+                            own_book = stream.next()? == "true";
+                        }
+                        "BookFile" => {
+                            let path = (&mut stream).collect::<Vec<_>>().join(" ");
+                            book = None;
+                            if !path.is_empty() && path != "<empty>" {
+                                match Book::load(&path) {
+                                    Ok(b) => book = Some(b),
+                                    Err(e) => eprintln!("Failed to load book {}: {}", path, e),
+                                }
+                            }
+                        }
+                        "BookSelection" => {
+                            book_selection = match stream.next()? {
+                                "Best" => BookSelection::Best,
+                                _ => BookSelection::WeightedRandom,
+                            };
+                        }
+                        #[cfg(feature = "syzygy")]
+                        "SyzygyPath" => {
+                            let mut tb = frozenight::Tablebase::new();
+                            let path = (&mut stream).collect::<Vec<_>>().join(" ");
+                            if !path.is_empty() && path != "<empty>" {
+                                for dir in path.split(if cfg!(windows) { ';' } else { ':' }) {
+                                    if let Err(e) = tb.add_directory(dir) {
+                                        eprintln!(
+                                            "Failed to load tablebases from {}: {:?}",
+                                            dir, e
+                                        );
+                                    }
+                                }
+                            }
+                            frozenight.set_tablebase(tb);
+                        }
                         _ =>
                         {
                             #[cfg(feature = "tweakable")]
@@ -142,18 +242,42 @@ fn main() {
                         }),
                     );
                 }
+                "eval" => {
+                    let trace = frozenight::trace_eval(frozenight.board());
+                    println!("network:          {}", trace.network);
+                    println!("fifty-move scale: {}/100", trace.fifty_move_scale);
+                    println!("endgame scale:    {}/64", trace.endgame_scale);
+                    println!("eval (white):     {}", trace.eval);
+                }
                 "go" => {
                     let mut clock = None;
                     let mut increment = Duration::ZERO;
                     let mut use_all_time = true;
                     let mut nodes = u64::MAX;
                     let mut moves_to_go = None;
+                    let mut pondering = false;
+                    let mut searchmoves = vec![];
 
                     let mut depth = 250;
 
                     let stm = frozenight.board().side_to_move();
                     while let Some(param) = stream.next() {
                         match param {
+                            "perft" => {
+                                let depth = stream.next()?.parse().ok()?;
+                                let board = frozenight.board();
+                                let divide = perft::perft_divide(board, depth);
+                                for &(mv, nodes) in &divide {
+                                    println!("{}: {}", to_uci_castling(board, mv, chess960), nodes);
+                                }
+                                let total: u64 = match depth {
+                                    0 => perft::perft(board, depth),
+                                    _ => divide.iter().map(|&(_, nodes)| nodes).sum(),
+                                };
+                                println!();
+                                println!("Nodes searched: {}", total);
+                                return None;
+                            }
                             "wtime" if stm == Color::White => {
                                 clock = Some(Duration::from_millis(
                                     stream.next().unwrap().parse().unwrap(),
@@ -185,55 +309,63 @@ fn main() {
                             }
                             "depth" => depth = stream.next().unwrap().parse().unwrap(),
                             "nodes" => nodes = stream.next().unwrap().parse().unwrap(),
+                            "ponder" => pondering = true,
+                            "searchmoves" => {
+                                while let Some(mv) = stream.peek().and_then(|s| s.parse().ok()) {
+                                    stream.next();
+                                    searchmoves.push(from_uci_castling(
+                                        frozenight.board(),
+                                        mv,
+                                        chess960,
+                                    ));
+                                }
+                            }
                             _ => {}
                         }
                     }
 
-                    let board1 = frozenight.board().clone();
-                    let board2 = frozenight.board().clone();
-                    frozenight.search(
-                        TimeConstraint {
-                            nodes,
-                            depth,
-                            clock,
-                            increment,
-                            overhead: move_overhead,
-                            moves_to_go,
-                            use_all_time,
-                        },
-                        move |info| {
-                            let time = now.elapsed();
-                            print!(
-                                "info depth {} seldepth {} nodes {} nps {} score {} time {} pv",
-                                info.depth,
-                                info.selective_depth,
-                                info.nodes,
-                                (info.nodes as f64 / time.as_secs_f64()).round() as u64,
-                                match ob_no_adj {
-                                    true => frozenight::Eval::new(250),
-                                    false => info.eval,
-                                },
-                                time.as_millis()
-                            );
-                            let mut board = board1.clone();
-                            for &mv in &info.pv {
-                                print!(" {}", to_uci_castling(&board, mv, chess960));
-                                board.play(mv);
-                            }
-                            println!();
-                        },
-                        move |info| {
-                            println!(
-                                "bestmove {}",
-                                to_uci_castling(&board2, info.best_move, chess960)
-                            );
-                            stdout().flush().unwrap();
-                        },
+                    let time = TimeConstraint {
+                        nodes,
+                        depth,
+                        clock,
+                        increment,
+                        overhead: move_overhead,
+                        moves_to_go,
+                        use_all_time,
+                        min_time,
+                    };
+
+                    // a restricted or pondering search is left to the engine
+                    let book_move = book
+                        .as_ref()
+                        .filter(|_| own_book && searchmoves.is_empty() && !pondering)
+                        .and_then(|book| book.pick(frozenight.board(), book_selection, random()));
+                    if let Some(mv) = book_move {
+                        let mv = to_uci_castling(frozenight.board(), mv, chess960);
+                        println!("bestmove {}", mv);
+                        stdout().flush().unwrap();
+                        return None;
+                    }
+
+                    if let Err(e) = frozenight.set_searchmoves(searchmoves) {
+                        eprintln!("Invalid searchmoves: {}", e);
+                        return None;
+                    }
+                    // a pondering search only reports its best move after ponderhit or stop
+                    frozenight.set_ponder(pondering);
+
+                    go(
+                        &mut frozenight,
+                        time,
+                        now,
+                        ob_no_adj,
+                        chess960,
+                        show_wdl,
+                        white_relative,
                     );
                 }
-                "stop" => {
-                    frozenight.abort();
-                }
+                "ponderhit" => frozenight.ponderhit(),
+                "stop" => frozenight.abort(),
                 _ => {}
             }
             None
@@ -241,30 +373,164 @@ fn main() {
     }
 }
 
-fn to_uci_castling(board: &Board, mut mv: Move, chess960: bool) -> Move {
-    if chess960 {
-        return mv;
+/// A random number for picking book moves, seeded from the clock.
+fn random() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    // the splitmix64 finalizer, so that close times give unrelated numbers
+    let mut z = nanos.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+/// The score and bound to report for a side-to-move relative `eval`, converted to white's
+/// perspective if `white_relative` is set.
+fn reported_score(
+    eval: Eval,
+    bound: Option<Bound>,
+    side_to_move: Color,
+    white_relative: bool,
+) -> (Eval, Option<Bound>) {
+    if !white_relative || side_to_move == Color::White {
+        return (eval, bound);
     }
-    if board.color_on(mv.from) == board.color_on(mv.to) {
-        if mv.to.file() > mv.from.file() {
-            mv.to = Square::new(File::G, mv.to.rank());
-        } else {
-            mv.to = Square::new(File::C, mv.to.rank());
+    // a bound on the eval flips along with it when negated for white's perspective
+    let bound = bound.map(|bound| match bound {
+        Bound::Lower => Bound::Upper,
+        Bound::Upper => Bound::Lower,
+    });
+    (eval.white_relative(side_to_move), bound)
+}
+
+/// Starts a search, printing info lines and the best move as it goes.
+fn go(
+    frozenight: &mut MtFrozenight,
+    time: TimeConstraint,
+    start: Instant,
+    ob_no_adj: bool,
+    chess960: bool,
+    show_wdl: bool,
+    white_relative: bool,
+) {
+    let board1 = frozenight.board().clone();
+    let board2 = frozenight.board().clone();
+    let board3 = frozenight.board().clone();
+    frozenight.set_currmove_handler(move |mv, number| {
+        // only worth reporting once the search has been going for a while
+        if start.elapsed() >= Duration::from_secs(1) {
+            println!(
+                "info currmove {} currmovenumber {}",
+                to_uci_castling(&board3, mv, chess960),
+                number
+            );
         }
-    }
-    mv
+    });
+    frozenight.search(
+        time,
+        move |info| {
+            let time = start.elapsed();
+            let lines = std::iter::once((info.eval, &info.pv))
+                .chain(info.other_pvs.iter().map(|(eval, pv)| (*eval, pv)));
+            for (i, (eval, pv)) in lines.enumerate() {
+                print!(
+                    "info depth {} seldepth {}",
+                    info.depth, info.selective_depth
+                );
+                if !info.other_pvs.is_empty() {
+                    print!(" multipv {}", i + 1);
+                }
+                let eval = match ob_no_adj {
+                    true => Eval::new(250),
+                    false => eval,
+                };
+                let (eval, bound) =
+                    reported_score(eval, info.bound, board1.side_to_move(), white_relative);
+                print!(
+                    " nodes {} nps {} hashfull {} score {}",
+                    info.nodes,
+                    (info.nodes as f64 / time.as_secs_f64()).round() as u64,
+                    info.hashfull,
+                    eval,
+                );
+                match bound {
+                    Some(Bound::Lower) => print!(" lowerbound"),
+                    Some(Bound::Upper) => print!(" upperbound"),
+                    None => {}
+                }
+                if show_wdl {
+                    let (w, d, l) = eval.to_wdl();
+                    print!(" wdl {} {} {}", w, d, l);
+                }
+                print!(" time {} pv", time.as_millis());
+                let mut board = board1.clone();
+                for &mv in pv {
+                    print!(" {}", to_uci_castling(&board, mv, chess960));
+                    board.play(mv);
+                }
+                println!();
+            }
+        },
+        move |info| {
+            if !board2.generate_moves(|_| true) {
+                // checkmate or stalemate, so there is no move to play
+                println!("info depth 0 score {}", info.eval);
+                println!("bestmove 0000");
+                stdout().flush().unwrap();
+                return;
+            }
+            let best_move = to_uci_castling(&board2, info.best_move, chess960);
+            match *info.pv.as_slice() {
+                [mv, reply, ..] if mv == info.best_move => {
+                    let mut board = board2.clone();
+                    board.play(mv);
+                    println!(
+                        "bestmove {} ponder {}",
+                        best_move,
+                        to_uci_castling(&board, reply, chess960)
+                    );
+                }
+                _ => println!("bestmove {}", best_move),
+            }
+            stdout().flush().unwrap();
+        },
+    );
 }
 
-fn from_uci_castling(board: &Board, mut mv: Move, chess960: bool) -> Move {
-    if chess960 {
-        return mv;
-    }
-    if mv.from.file() == File::E && board.piece_on(mv.from) == Some(Piece::King) {
-        if mv.to.file() == File::G {
-            mv.to = Square::new(File::H, mv.to.rank());
-        } else if mv.to.file() == File::C {
-            mv.to = Square::new(File::A, mv.to.rank());
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_stay_relative_to_the_side_to_move() {
+        let eval = Eval::new(500);
+        for side in [Color::White, Color::Black] {
+            for bound in [None, Some(Bound::Lower), Some(Bound::Upper)] {
+                assert_eq!(reported_score(eval, bound, side, false), (eval, bound));
+            }
         }
     }
-    mv
+
+    #[test]
+    fn white_relative_scores_flip_for_black() {
+        let eval = Eval::new(500);
+        assert_eq!(
+            reported_score(eval, Some(Bound::Lower), Color::White, true),
+            (eval, Some(Bound::Lower))
+        );
+        assert_eq!(
+            reported_score(eval, None, Color::Black, true),
+            (-eval, None)
+        );
+        // black's score being at least +100 means white's is at most -100
+        assert_eq!(
+            reported_score(eval, Some(Bound::Lower), Color::Black, true),
+            (-eval, Some(Bound::Upper))
+        );
+        assert_eq!(
+            reported_score(eval, Some(Bound::Upper), Color::Black, true),
+            (-eval, Some(Bound::Lower))
+        );
+    }
 }
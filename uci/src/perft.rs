@@ -0,0 +1,101 @@
+use cozy_chess::{Board, Move};
+
+/// Counts the leaf nodes of the legal move tree of the given depth.
+pub fn perft(board: &Board, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let mut nodes = 0;
+    board.generate_moves(|mvs| {
+        if depth == 1 {
+            nodes += mvs.len() as u64;
+        } else {
+            for mv in mvs {
+                let mut child = board.clone();
+                child.play_unchecked(mv);
+                nodes += perft(&child, depth - 1);
+            }
+        }
+        false
+    });
+    nodes
+}
+
+/// Like [`perft`], but reports the node count of each root move separately. At depth 0 the root
+/// is the only leaf, so there are no moves to divide it by.
+pub fn perft_divide(board: &Board, depth: u32) -> Vec<(Move, u64)> {
+    let mut result = vec![];
+    if depth == 0 {
+        return result;
+    }
+    board.generate_moves(|mvs| {
+        for mv in mvs {
+            let mut child = board.clone();
+            child.play_unchecked(mv);
+            result.push((mv, perft(&child, depth - 1)));
+        }
+        false
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use cozy_chess::Board;
+
+    use super::{perft, perft_divide};
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    #[test]
+    fn startpos() {
+        let board = Board::default();
+        let expected = [
+            (0, 1),
+            (1, 20),
+            (2, 400),
+            (3, 8902),
+            (4, 197281),
+            (5, 4865609),
+        ];
+        for (depth, nodes) in expected {
+            assert_eq!(perft(&board, depth), nodes, "depth {}", depth);
+        }
+    }
+
+    #[test]
+    fn kiwipete() {
+        let board: Board = KIWIPETE.parse().unwrap();
+        for (depth, nodes) in [(1, 48), (2, 2039), (3, 97862), (4, 4085603)] {
+            assert_eq!(perft(&board, depth), nodes, "depth {}", depth);
+        }
+    }
+
+    // too slow to run by default
+    #[test]
+    #[ignore]
+    fn kiwipete_depth_5() {
+        let board: Board = KIWIPETE.parse().unwrap();
+        assert_eq!(perft(&board, 5), 193690690);
+    }
+
+    #[test]
+    fn divide() {
+        let board: Board = KIWIPETE.parse().unwrap();
+        let divide = perft_divide(&board, 3);
+        assert_eq!(divide.len(), 48);
+        assert_eq!(divide.iter().map(|&(_, n)| n).sum::<u64>(), 97862);
+
+        for (mv, nodes) in divide {
+            let mut child = board.clone();
+            child.play_unchecked(mv);
+            assert_eq!(perft(&child, 2), nodes, "{}", mv);
+        }
+    }
+
+    #[test]
+    fn divide_depth_0() {
+        assert!(perft_divide(&Board::default(), 0).is_empty());
+    }
+}